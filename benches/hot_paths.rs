@@ -0,0 +1,133 @@
+//! Baselines for the hot paths of the search: move generation, the
+//! heuristics and a fixed-depth search, each over the same positions.
+//! Run with `cargo bench`, or e.g. `cargo bench -- child_boards` for one.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use mankalah::{
+    board::PlayerMove,
+    eval::{AlphaBeta, Evaluator, Search},
+    game::{random_opening, Game},
+    heuristics::{fixed_weights, weighted_heuristic, Weights, HEURISTICS},
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+/// Entries in the transposition table of the searches, as many as the
+/// in-process players'. Each iteration gets a new table, which isn't timed.
+const TT_ENTRIES: usize = 1 << 16;
+
+const SEARCH_DEPTH: usize = 6;
+
+/// The start, an opening, a middlegame and an endgame
+fn positions() -> Vec<(&'static str, Game)> {
+    let mut rng = StdRng::seed_from_u64(0);
+    vec![
+        ("start", Game::default()),
+        ("opening", random_opening(&mut rng, 6).unwrap()),
+        ("middlegame", random_opening(&mut rng, 20).unwrap()),
+        ("endgame", random_opening(&mut rng, 40).unwrap()),
+    ]
+}
+
+fn weights() -> Weights {
+    "score=1.0 capture=0.6 chaining=0.95 hoarding=0.59"
+        .parse()
+        .unwrap()
+}
+
+/// The first pit the player to move can sow from
+fn first_pit(game: &Game) -> PlayerMove {
+    game.legal_moves()
+        .find(|the_move| *the_move != PlayerMove::Swap)
+        .unwrap()
+}
+
+fn board(c: &mut Criterion) {
+    for (name, game) in positions() {
+        let the_move = first_pit(&game);
+        let n = match the_move {
+            PlayerMove::Move { n } => n,
+            PlayerMove::Swap => unreachable!(),
+        };
+        c.bench_with_input(BenchmarkId::new("sow_seeds", name), &game, |b, game| {
+            b.iter(|| {
+                let mut board = game.board;
+                black_box(board.sow_seeds(game.to_move, n))
+            })
+        });
+        c.bench_with_input(BenchmarkId::new("apply_move", name), &game, |b, game| {
+            b.iter(|| {
+                let mut board = game.board;
+                black_box(board.apply_move(black_box(the_move), game.to_move, game.first_move))
+            })
+        });
+        c.bench_with_input(BenchmarkId::new("child_boards", name), &game, |b, game| {
+            b.iter(|| {
+                game.board
+                    .child_boards(game.to_move, game.first_move)
+                    .for_each(|child| {
+                        black_box(child);
+                    })
+            })
+        });
+    }
+}
+
+fn heuristics(c: &mut Criterion) {
+    let weights = fixed_weights(weights());
+    for (name, game) in positions() {
+        for heuristic in HEURISTICS.iter() {
+            let id = BenchmarkId::new(format!("heuristic/{}", heuristic.name()), name);
+            c.bench_with_input(id, &game, |b, game| {
+                b.iter(|| black_box(heuristic.evaluate(black_box(&game.board), game.to_move)))
+            });
+        }
+        c.bench_with_input(
+            BenchmarkId::new("weighted_heuristic", name),
+            &game,
+            |b, game| {
+                b.iter(|| {
+                    black_box(weighted_heuristic(
+                        weights,
+                        black_box(&game.board),
+                        game.to_move,
+                    ))
+                })
+            },
+        );
+    }
+}
+
+fn search(c: &mut Criterion) {
+    let weights = weights();
+    let mut group = c.benchmark_group("alpha_beta");
+    group.sample_size(20);
+    for (name, game) in positions() {
+        group.bench_with_input(
+            BenchmarkId::new(format!("depth_{}", SEARCH_DEPTH), name),
+            &game,
+            |b, game| {
+                // a fresh table, so that every iteration searches the same
+                // tree, made outside the timed part
+                b.iter_batched(
+                    || Search::with_tt_entries(TT_ENTRIES),
+                    |mut search| {
+                        black_box(AlphaBeta::eval(
+                            &mut search,
+                            game.board,
+                            game.to_move,
+                            0,
+                            game.first_move,
+                            SEARCH_DEPTH,
+                            weights,
+                        ))
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, board, heuristics, search);
+criterion_main!(benches);
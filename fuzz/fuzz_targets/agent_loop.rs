@@ -0,0 +1,25 @@
+//! Play a match against whatever the engine sends. The agent may give up with
+//! an error, but must not panic, and everything it sends must be a message.
+//!
+//! `cargo +nightly fuzz run agent_loop -- -dict=fuzz/protocol.dict`
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use mankalah::{agent::Agent, eval::Algorithm, game::PlayerConfig, grammar::ProtocolGrammar};
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let player = PlayerConfig::new(Algorithm::AlphaBeta, 2, "score=1.0".parse().unwrap());
+    let mut agent = Agent::with_tt_entries(1 << 10);
+    let mut output = Vec::new();
+    let _ = agent.play_match(&player, Cursor::new(data), &mut output);
+
+    let output = String::from_utf8(output).unwrap();
+    for line in output.split_inclusive('\n') {
+        assert!(
+            ProtocolGrammar::AgentMessage(line).is_ok(),
+            "sent {:?}",
+            line
+        );
+    }
+});
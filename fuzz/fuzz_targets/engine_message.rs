@@ -0,0 +1,28 @@
+//! Parse anything as a message from the engine. Whatever parses must display
+//! as text that parses back to the same message.
+//!
+//! `cargo +nightly fuzz run engine_message -- -dict=fuzz/protocol.dict`
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use mankalah::grammar::ProtocolGrammar;
+use std::str;
+
+fuzz_target!(|data: &[u8]| {
+    let text = match str::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    if let Ok(message) = ProtocolGrammar::EngineMessage(text) {
+        assert_eq!(
+            ProtocolGrammar::EngineMessage(&message.to_string()),
+            Ok(message)
+        );
+    }
+    if let Ok(message) = ProtocolGrammar::AgentMessage(text) {
+        assert_eq!(
+            ProtocolGrammar::AgentMessage(&message.to_string()),
+            Ok(message)
+        );
+    }
+});
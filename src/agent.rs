@@ -1,184 +1,671 @@
 use crate::{
-    board::{BoardState, PlayerMove, PlayerState, Position},
-    eval::Evaluator,
+    board::{PlayerMove, PlayerState, Position},
+    eval::Search,
+    game::{Game, Limit, PlayerConfig},
     grammar::ProtocolGrammar,
-    heuristics::{Score, Weights},
+    heuristics::{explain, MAX_PLY},
     protocol::*,
+    tt::DEFAULT_TT_ENTRIES,
 };
 use std::{
-    io::BufRead,
-    time::{Duration, Instant},
+    fmt,
+    io::{self, BufRead, Write},
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
 };
 
-fn read_line() -> String {
-    let mut line = String::new();
-    let stdin = std::io::stdin();
-    stdin.lock().read_line(&mut line).unwrap();
-    line
+/// How deep the opponent's replies are predicted when pondering
+const PREDICTION_DEPTH: usize = 6;
+
+/// Why a match over the protocol couldn't go on
+#[derive(Debug)]
+pub enum AgentError {
+    Io(io::Error),
+    /// A line that isn't a message from the engine
+    InvalidMessage {
+        line: String,
+        error: String,
+    },
+    /// A message that doesn't follow from the match so far
+    Protocol(String),
+    /// The engine hung up before the match was over
+    Disconnected,
+}
+
+impl fmt::Display for AgentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::InvalidMessage { line, error } => {
+                write!(f, "invalid message `{}`: {}", line, error)
+            }
+            Self::Protocol(err) => write!(f, "{}", err),
+            Self::Disconnected => write!(f, "the engine hung up before the match was over"),
+        }
+    }
+}
+
+impl std::error::Error for AgentError {}
+
+impl From<io::Error> for AgentError {
+    fn from(err: io::Error) -> Self { Self::Io(err) }
+}
+
+/// Where the agent is in a match
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Phase {
+    /// Waiting for `START`
+    Starting,
+    Playing,
+    Over,
 }
 
-fn read_engine_message() -> EngineMessage {
-    let line = read_line();
-    ProtocolGrammar::EngineMessage(&line).unwrap()
+/// What pondering found: the position we expect to move in next, and the
+/// deepest search of it that finished
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pondered {
+    game: Option<Game>,
+    finished: Option<(usize, PlayerMove)>,
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Predict the opponent's replies in `game` until it is `us` to move, then
+/// search that position deeper and deeper until `player`'s depth, or until
+/// `search` is stopped
+fn ponder(player: &PlayerConfig, search: &mut Search, mut game: Game, us: Position) -> Pondered {
+    while game.to_move != us && game.result().is_none() {
+        match player.search_to(search, &game, PREDICTION_DEPTH) {
+            Some(reply) if !search.stopped() => game.play(reply),
+            _ => {
+                return Pondered {
+                    game: None,
+                    finished: None,
+                }
+            }
+        }
+    }
+    if game.result().is_some() {
+        return Pondered {
+            game: None,
+            finished: None,
+        };
+    }
+    let max_depth = match player.limit {
+        Limit::Depth(depth) => depth,
+        Limit::Time(_) => MAX_PLY,
+    };
+    let mut finished = None;
+    for depth in 1..=max_depth {
+        match player.search_to(search, &game, depth) {
+            Some(best_move) if !search.stopped() => finished = Some((depth, best_move)),
+            _ => break,
+        }
+    }
+    Pondered {
+        game: Some(game),
+        finished,
+    }
+}
+
+/// A search on the opponent's time, which has the agent's `Search` until it is
+/// stopped
+#[derive(Debug)]
+struct Ponder {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<(Search, Pondered)>,
+}
+
+/// How often pondering predicted the opponent's move
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PonderStats {
+    pub ponders: u32,
+    /// Ponders that searched the position we then had to move in
+    pub hits: u32,
+    /// Hits that had already searched deep enough, so we moved at once
+    pub reused: u32,
+}
+
+impl fmt::Display for PonderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ponders = {}, hits = {} ({:.0}%), reused = {}",
+            self.ponders,
+            self.hits,
+            100.0 * self.hits as f64 / self.ponders.max(1) as f64,
+            self.reused
+        )
+    }
+}
+
+/// Plays a match over the engine's protocol. The match is kept as the
+/// engine sees it, by the sides of the board: a swap moves the players, not
+/// the seeds, so the engine never tells the player who swapped.
+#[derive(Debug)]
 pub struct Agent {
+    /// The board, the side to move and whether the pie rule can still be
+    /// used. A swap is never played on it, see `Agent::play`.
+    game: Game,
+    /// The side we are on
     position: Position,
-    state: BoardState,
-    first_move: bool,
+    phase: Phase,
+    search: Search,
+    explain: bool,
+    ponder: bool,
+    pondering: Option<Ponder>,
+    pub ponder_stats: PonderStats,
 }
 
 impl Default for Agent {
-    fn default() -> Self {
+    fn default() -> Self { Self::with_tt_entries(DEFAULT_TT_ENTRIES) }
+}
+
+impl Drop for Agent {
+    fn drop(&mut self) { self.stop_pondering(); }
+}
+
+impl Agent {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn with_tt_entries(n_entries: usize) -> Self {
         Self {
+            game: Game::default(),
             position: Position::South,
-            state: BoardState::default(),
-            first_move: true,
+            phase: Phase::Starting,
+            search: Search::with_tt_entries(n_entries),
+            explain: false,
+            ponder: false,
+            pondering: None,
+            ponder_stats: PonderStats::default(),
         }
     }
-}
 
-impl Agent {
-    pub fn new() -> Self { Self::default() }
+    /// The board as the engine last told us, and who is to move on it
+    pub fn game(&self) -> Game { self.game }
 
-    pub fn our_state(&self) -> PlayerState { self.state[self.position] }
+    pub fn position(&self) -> Position { self.position }
 
-    pub fn can_swap(&self) -> bool { self.first_move && self.position == Position::North }
+    pub fn is_over(&self) -> bool { self.phase == Phase::Over }
 
-    fn send_move(&mut self, chosen_move: PlayerMove) {
-        // if let PlayerMove::Move { .. } = chosen_move {
-        //   self.state.apply_move( chosen_move, self.position, true);
-        // }
-        print!("{}", chosen_move);
+    /// Forget the match, to start another. The transposition table is kept.
+    pub fn reset(&mut self) {
+        self.stop_pondering();
+        self.game = Game::default();
+        self.position = Position::South;
+        self.phase = Phase::Starting;
     }
 
-    fn get_move<E: Evaluator>(&self, max_depth: usize, weights: Weights) -> PlayerMove {
-        let (chosen_move, _) = E::eval(
-            self.state,
-            self.position,
-            0,
-            self.first_move,
-            max_depth,
-            weights,
-        );
-        chosen_move.unwrap()
-    }
-
-    fn make_move<E: Evaluator>(&mut self, max_depth: usize, weights: Weights) -> bool {
-        log::debug!("Getting move: pos = {:?}", self.position);
-        // let start = Instant::now();
-        // let mut depth = 5;
-        // let chosen_move = loop {
-        //   let picked_move = self.get_move::<E>(depth, weights);
-        //   if start.elapsed().as_secs() > 3 || depth >= 30 {
-        //     break picked_move;
-        //   }
-        //   depth += 1;
-        // };
-        let chosen_move = self.get_move::<E>(max_depth, weights);
-        log::debug!("chosen_move = {:?}", chosen_move);
-
-        // Does not look like the engine tells us if we swap
-        let swapped = if let PlayerMove::Swap = chosen_move {
-            self.swap_sides();
-            log::debug!("Done swap {:?}", self.position);
-            true
-        } else {
-            false
-        };
-        self.send_move(chosen_move);
-        self.first_move = false;
-        swapped
+    pub fn our_state(&self) -> PlayerState { self.game.board[self.position] }
+
+    pub fn can_swap(&self) -> bool {
+        self.our_turn() && self.game.first_move && self.position == Position::North
+    }
+
+    fn our_turn(&self) -> bool {
+        self.phase == Phase::Playing && self.game.to_move == self.position
     }
 
-    fn swap_sides(&mut self) {
-        self.position = match self.position {
-            Position::North => Position::South,
-            Position::South => Position::North,
+    /// Log the evaluation of the principal leaf of every move we make
+    pub fn set_explain(&mut self, explain: bool) { self.explain = explain; }
+
+    /// Search while the opponent is thinking, for the position we expect them
+    /// to leave us in
+    pub fn set_ponder(&mut self, ponder: bool) { self.ponder = ponder; }
+
+    /// Play `the_move` for the side to move. `Game::play` swaps by mirroring
+    /// the board, as `Position` names a player, but here it names a side, so
+    /// the players change sides instead. Either way the player who made the
+    /// first move, now on North, moves next.
+    fn play(&mut self, the_move: PlayerMove) {
+        match the_move {
+            PlayerMove::Swap => {
+                self.position = !self.position;
+                self.game.to_move = Position::North;
+                self.game.first_move = false;
+            }
+            PlayerMove::Move { .. } => self.game.play(the_move),
         }
     }
 
-    fn set_state(&mut self, engine_state: BoardState) {
-        log::debug!(
-            "our state: {:?}, engine_state: {:?}",
-            self.state,
-            engine_state
-        );
-        assert_eq!(self.state, engine_state);
-        self.state = engine_state;
+    /// Ponder on the opponent's time, from the current position
+    fn start_pondering(&mut self, player: &PlayerConfig) {
+        let game = self.game;
+        // a swap can't be predicted, as `Game` would mirror the board
+        if !self.ponder || (game.first_move && game.to_move == Position::North) {
+            return;
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        // the ponder thread has the transposition table until it stops
+        let mut search = mem::replace(&mut self.search, Search::with_tt_entries(1));
+        search.stop = Some(stop.clone());
+        let player = player.clone();
+        let us = self.position;
+        let thread = thread::spawn(move || {
+            let pondered = ponder(&player, &mut search, game, us);
+            (search, pondered)
+        });
+        self.ponder_stats.ponders += 1;
+        self.pondering = Some(Ponder { stop, thread });
+    }
+
+    /// Stop pondering, if we are, and take the search back
+    fn stop_pondering(&mut self) -> Option<Pondered> {
+        let Ponder { stop, thread } = self.pondering.take()?;
+        stop.store(true, Ordering::Relaxed);
+        let (mut search, pondered) = thread.join().unwrap();
+        search.stop = None;
+        self.search = search;
+        log::debug!("pondered {:?}", pondered);
+        Some(pondered)
+    }
+
+    fn get_move(&mut self, player: &PlayerConfig, pondered: Option<Pondered>) -> PlayerMove {
+        let game = self.game;
+        let finished = pondered.and_then(|pondered| {
+            let hit = pondered.game == Some(game);
+            if hit {
+                self.ponder_stats.hits += 1;
+                match (player.limit, pondered.finished) {
+                    (Limit::Depth(depth), Some((finished, _))) if finished >= depth => {
+                        self.ponder_stats.reused += 1
+                    }
+                    _ => {}
+                }
+            }
+            log::info!(
+                "ponder {}: {}",
+                if hit { "hit" } else { "miss" },
+                self.ponder_stats
+            );
+            pondered.finished.filter(|_| hit)
+        });
+        let chosen_move = player.choose_move_after(&mut self.search, &game, finished);
+        log::info!("search stats: {}", self.search.stats);
+        if self.explain {
+            if let Some((leaf, leaf_pos)) = self.search.principal_leaf() {
+                log::info!(
+                    "principal leaf: {}, {} to move\n{}",
+                    leaf,
+                    leaf_pos,
+                    explain(player.weights, &leaf, leaf_pos)
+                );
+            }
+        }
+        chosen_move
+    }
+
+    /// Our move, if it is our turn, otherwise start pondering
+    fn next_move(
+        &mut self,
+        player: &PlayerConfig,
+        pondered: Option<Pondered>,
+    ) -> Option<PlayerMove> {
+        if !self.our_turn() {
+            self.start_pondering(player);
+            return None;
+        }
+        let chosen_move = self.get_move(player, pondered);
+        log::debug!("chosen move: {:?}", chosen_move);
+        // the engine only tells the other player about a swap
+        if chosen_move == PlayerMove::Swap {
+            self.play(chosen_move);
+            self.start_pondering(player);
+        }
+        Some(chosen_move)
     }
 
-    pub fn run<E: Evaluator>(&mut self, max_depth: usize, weights: Weights) {
-        let mut message = read_engine_message();
-        let mut was_our_move = false;
-        match message {
-            EngineMessage::NewMatch { pos } => {
+    /// Follow a message from the engine, and answer it with our move if it is
+    /// our turn
+    pub fn handle(
+        &mut self,
+        player: &PlayerConfig,
+        message: EngineMessage,
+    ) -> Result<Option<PlayerMove>, AgentError> {
+        let pondered = self.stop_pondering();
+        match (self.phase, message) {
+            (Phase::Starting, EngineMessage::NewMatch { pos }) => {
+                self.game = Game::default();
                 self.position = pos;
-                if pos == Position::South {
-                    self.make_move::<E>(max_depth, weights);
-                    was_our_move = true;
+                self.phase = Phase::Playing;
+                Ok(self.next_move(player, None))
+            }
+            (
+                Phase::Playing,
+                EngineMessage::StateChange {
+                    player_move,
+                    state,
+                    turn,
+                },
+            ) => {
+                let mover = self.game.to_move;
+                let legal = self.game.legal_moves().any(|legal| legal == player_move);
+                // we already played our swap, which the engine doesn't echo
+                if !legal || (player_move == PlayerMove::Swap && mover == self.position) {
+                    return Err(AgentError::Protocol(format!(
+                        "{:?} by {} is not legal in {}",
+                        player_move, mover, self.game.board
+                    )));
+                }
+                self.play(player_move);
+
+                let (expected, expected_turn) = if self.game.referee_over() {
+                    (self.game.swept(), Turn::End)
+                } else if self.our_turn() {
+                    (self.game.board, Turn::You)
+                } else {
+                    (self.game.board, Turn::Opponent)
+                };
+                if state != expected || turn != expected_turn {
+                    return Err(AgentError::Protocol(format!(
+                        "after {:?} by {} the engine says {} with turn {:?}, but we expected {} \
+                         with turn {:?}",
+                        player_move, mover, state, turn, expected, expected_turn
+                    )));
+                }
+                if turn == Turn::End {
+                    self.phase = Phase::Over;
+                    return Ok(None);
                 }
+                Ok(self.next_move(player, pondered))
             }
-            EngineMessage::GameOver => {
-                return;
+            (_, EngineMessage::GameOver) => {
+                self.phase = Phase::Over;
+                Ok(None)
             }
-            _ => unreachable!(),
+            (phase, message) => Err(AgentError::Protocol(format!(
+                "unexpected {:?} while {:?}",
+                message, phase
+            ))),
         }
+    }
+
+    /// Play a match, reading the engine's messages from `input` and writing
+    /// our moves to `output`, choosing them as `player` does
+    pub fn play_match(
+        &mut self,
+        player: &PlayerConfig,
+        mut input: impl BufRead,
+        mut output: impl Write,
+    ) -> Result<(), AgentError> {
+        let mut line = String::new();
+        while !self.is_over() {
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                return Err(AgentError::Disconnected);
+            }
+            let message = ProtocolGrammar::EngineMessage(&line).map_err(|err| {
+                AgentError::InvalidMessage {
+                    line: line.trim_end().to_owned(),
+                    error: err.to_string(),
+                }
+            })?;
+            if let Some(the_move) = self.handle(player, message)? {
+                write!(output, "{}", AgentMessage::from(the_move))?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
 
+    /// Play a match over the protocol on stdin and stdout
+    pub fn run(&mut self, player: &PlayerConfig) -> Result<(), AgentError> {
+        let stdin = io::stdin();
+        self.play_match(player, stdin.lock(), io::stdout())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::board::BoardState;
+    use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
+    use std::io::Cursor;
+
+    fn player() -> PlayerConfig {
+        PlayerConfig::new(
+            crate::eval::Algorithm::AlphaBeta,
+            3,
+            "score=1.0 capture=0.6".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn ponder_searches_after_the_predicted_reply() {
+        let mut search = Search::with_tt_entries(1 << 10);
+        let pondered = ponder(&player(), &mut search, Game::default(), Position::North);
+        let game = pondered.game.unwrap();
+        assert_eq!(game.to_move, Position::North);
+        assert_ne!(game.board, Game::default().board);
+
+        let (depth, best_move) = pondered.finished.unwrap();
+        assert_eq!(depth, 3);
+        let mut fresh = Search::with_tt_entries(1 << 10);
+        assert_eq!(player().search_to(&mut fresh, &game, 3), Some(best_move));
+    }
+
+    #[test]
+    fn stopped_ponders_find_nothing() {
+        let mut search = Search::with_tt_entries(1 << 10);
+        search.stop = Some(Arc::new(AtomicBool::new(true)));
+        let pondered = ponder(&player(), &mut search, Game::default(), Position::North);
+        assert_eq!(
+            pondered,
+            Pondered {
+                game: None,
+                finished: None
+            }
+        );
+    }
+
+    /// Send `message` to `agent` as protocol text, and its reply back
+    fn send(
+        agent: &mut Agent,
+        player: &PlayerConfig,
+        message: EngineMessage,
+    ) -> Result<Option<PlayerMove>, AgentError> {
+        let message = ProtocolGrammar::EngineMessage(&message.to_string()).unwrap();
+        let reply = agent.handle(player, message)?;
+        Ok(reply.map(|the_move| {
+            let text = AgentMessage::from(the_move).to_string();
+            ProtocolGrammar::AgentMessage(&text).unwrap().into()
+        }))
+    }
+
+    /// Referee a match between `agent`, starting on `us`, and `opponent`, as
+    /// the engine would: the board is sent by sides, a swap is only sent to
+    /// the other player, and the match ends as soon as either side is empty.
+    /// Returns who made each move, by the side they started on.
+    fn referee(
+        agent: &mut Agent,
+        player: &PlayerConfig,
+        us: Position,
+        mut opponent: impl FnMut(&Game) -> PlayerMove,
+    ) -> Result<Vec<(Position, PlayerMove)>, AgentError> {
+        let mut game = Game::default();
+        let mut swapped = false;
+        let mut moves = Vec::new();
+        let mut reply = send(agent, player, EngineMessage::NewMatch { pos: us })?;
         loop {
-            message = read_engine_message();
-            let our_turn;
-            match message {
-                EngineMessage::GameOver => {
-                    return;
+            let mover = game.to_move;
+            let the_move = if mover == us {
+                reply.take().expect("the agent didn't move on its turn")
+            } else {
+                assert_eq!(reply, None, "the agent moved out of turn");
+                opponent(&game)
+            };
+            assert!(game.legal_moves().any(|legal| legal == the_move));
+            game.play(the_move);
+            swapped ^= the_move == PlayerMove::Swap;
+            moves.push((mover, the_move));
+
+            let over = game.referee_over();
+            let board = if over { game.swept() } else { game.board };
+            let state = if swapped {
+                BoardState {
+                    north: board.south,
+                    south: board.north,
                 }
+            } else {
+                board
+            };
+            let turn = match () {
+                _ if over => Turn::End,
+                _ if game.to_move == us => Turn::You,
+                _ => Turn::Opponent,
+            };
+            if the_move == PlayerMove::Swap && mover == us {
+                continue;
+            }
+            reply = send(
+                agent,
+                player,
                 EngineMessage::StateChange {
-                    player_move,
+                    player_move: the_move,
                     state,
                     turn,
-                } => {
-                    log::debug!("state: {:?}", self.state);
-                    log::debug!("our pos {:?}", self.position);
-                    match player_move {
-                        PlayerMove::Swap => {
-                            log::debug!("swappy?");
-                            self.swap_sides();
-                        }
-                        PlayerMove::Move { .. } => {
-                            let move_pos = if was_our_move {
-                                self.position
-                            } else {
-                                !self.position
-                            };
-                            log::debug!(
-                                "here? {:?} {:?} -- us {:?} {:?}",
-                                player_move,
-                                move_pos,
-                                self.position,
-                                was_our_move
-                            );
-                            self.state.apply_move(player_move, move_pos, false);
-                        }
-                    }
-                    self.set_state(state);
-
-                    our_turn = match turn {
-                        Turn::You => true,
-                        Turn::Opponent => {
-                            was_our_move = false;
-                            false
-                        }
-                        Turn::End => {
-                            return;
-                        }
-                    }
-                }
-                _ => unreachable!(),
+                },
+            )?;
+            if over {
+                assert!(agent.is_over());
+                return Ok(moves);
+            }
+            assert_eq!(agent.game().board, state);
+            // the agent changes sides as soon as it swaps
+            let swapping = reply == Some(PlayerMove::Swap);
+            assert_eq!(agent.position(), if swapped ^ swapping { !us } else { us });
+        }
+    }
+
+    fn random_opponent(seed: u64) -> impl FnMut(&Game) -> PlayerMove {
+        let mut rng = StdRng::seed_from_u64(seed);
+        move |game| {
+            game.legal_moves()
+                .filter(|&the_move| the_move != PlayerMove::Swap)
+                .choose(&mut rng)
+                .unwrap()
+        }
+    }
+
+    #[test]
+    fn follows_matches_with_extra_turns() {
+        let mut extra_turns = 0;
+        for seed in 0..4 {
+            for &us in &[Position::South, Position::North] {
+                let mut agent = Agent::with_tt_entries(1 << 10);
+                let moves = referee(&mut agent, &player(), us, random_opponent(seed)).unwrap();
+                extra_turns += moves
+                    .windows(2)
+                    .filter(|pair| pair[0].0 == us && pair[1].0 == us)
+                    .count();
+            }
+        }
+        assert!(extra_turns > 0);
+    }
+
+    #[test]
+    fn follows_the_opponent_swapping() {
+        let mut agent = Agent::with_tt_entries(1 << 10);
+        let mut rng = random_opponent(1);
+        let moves = referee(&mut agent, &player(), Position::South, |game| {
+            if game.first_move && game.to_move == Position::North {
+                PlayerMove::Swap
+            } else {
+                rng(game)
             }
-            if our_turn {
-                was_our_move = !self.make_move::<E>(max_depth, weights);
+        })
+        .unwrap();
+        assert_eq!(moves[1], (Position::North, PlayerMove::Swap));
+        assert_eq!(moves[2].0, Position::South);
+    }
+
+    #[test]
+    fn follows_our_swap() {
+        let greedy = PlayerConfig::new(
+            crate::eval::Algorithm::AlphaBeta,
+            1,
+            "score=1.0".parse().unwrap(),
+        );
+        let mut agent = Agent::with_tt_entries(1 << 10);
+        let mut rng = random_opponent(2);
+        let moves = referee(&mut agent, &greedy, Position::North, |game| {
+            if game.first_move {
+                PlayerMove::Move { n: 0 }
+            } else {
+                rng(game)
             }
+        })
+        .unwrap();
+        assert_eq!(moves[1], (Position::North, PlayerMove::Swap));
+        assert_eq!(moves[2].0, Position::South);
+    }
+
+    #[test]
+    fn protocol_errors() {
+        let start = EngineMessage::NewMatch {
+            pos: Position::North,
+        };
+        let mut game = Game::default();
+        game.play(PlayerMove::Move { n: 2 });
+        let change = |state, turn| EngineMessage::StateChange {
+            player_move: PlayerMove::Move { n: 2 },
+            state,
+            turn,
+        };
+
+        let mut agent = Agent::with_tt_entries(1 << 10);
+        assert!(matches!(
+            agent.handle(&player(), change(game.board, Turn::You)),
+            Err(AgentError::Protocol(_))
+        ));
+
+        let mut agent = Agent::with_tt_entries(1 << 10);
+        assert_eq!(agent.handle(&player(), start).unwrap(), None);
+        assert!(matches!(
+            agent.handle(&player(), change(BoardState::default(), Turn::You)),
+            Err(AgentError::Protocol(_))
+        ));
+
+        let mut agent = Agent::with_tt_entries(1 << 10);
+        agent.handle(&player(), start).unwrap();
+        assert!(matches!(
+            agent.handle(&player(), change(game.board, Turn::Opponent)),
+            Err(AgentError::Protocol(_))
+        ));
+
+        let mut agent = Agent::with_tt_entries(1 << 10);
+        let input = format!("START;North\nCHANGE;3;{};YOU\n", game.board);
+        let input = Cursor::new(input);
+        let mut output = Vec::new();
+        assert!(matches!(
+            agent.play_match(&player(), input, &mut output),
+            Err(AgentError::Disconnected)
+        ));
+        assert!(output.starts_with(b"MOVE;") || output.starts_with(b"SWAP"));
+
+        let mut agent = Agent::with_tt_entries(1 << 10);
+        assert!(matches!(
+            agent.play_match(&player(), Cursor::new("MOVE;1\n"), Vec::new()),
+            Err(AgentError::InvalidMessage { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_messages_are_errors() {
+        let invalid = include_str!("../testdata/protocol_invalid.txt");
+        for line in invalid.lines().filter(|line| !line.starts_with('#')) {
+            let mut agent = Agent::with_tt_entries(1 << 10);
+            let input = format!("START;North\n{}\n", line);
+            assert!(
+                matches!(
+                    agent.play_match(&player(), Cursor::new(input), Vec::new()),
+                    Err(AgentError::InvalidMessage { .. })
+                ),
+                "{:?}",
+                line
+            );
         }
     }
 }
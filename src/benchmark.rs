@@ -1,130 +1,363 @@
-#![feature(format_args_capture)]
+use clap::{App, Arg, Error, ErrorKind};
+use mankalah::{
+    board::Position,
+    game::GameRecord,
+    profile::{Profile, Profiles, DEFAULT_PROFILES},
+    referee::RefereeLog,
+    results::{self, GameResult, CSV_HEADER},
+};
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
 
-use std::{fmt, process::Command};
+/// Results are appended to `<HISTORY_DIR>/<git revision>.csv`, compare runs
+/// with `mankalah compare`
+const HISTORY_DIR: &str = "history";
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum Winner {
-    Draw,
-    North,
-    South,
+/// Cores each game needs so that neither agent waits for the other's: one per
+/// agent, the referee is mostly idle
+const CORES_PER_GAME: usize = 2;
+
+/// An agent the referee runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ExternalAgent {
+    name: String,
+    program: PathBuf,
+    args: Vec<String>,
+}
+
+impl ExternalAgent {
+    /// The command line for the referee, which splits it on whitespace. A JVM
+    /// is limited to one core's worth of threads, otherwise it sizes its
+    /// garbage collector and compiler for the whole machine.
+    fn command(&self) -> String {
+        let mut words = vec![self.program.display().to_string()];
+        if self.program.file_stem() == Some(OsStr::new("java")) {
+            words.push("-XX:ActiveProcessorCount=1".to_owned());
+        }
+        words.extend(self.args.iter().cloned());
+        words.join(" ")
+    }
+}
+
+/// Agents from a file with one per line: a name, the program and its
+/// arguments, separated by whitespace. Everything after a `#` is a comment.
+fn read_agents(path: &Path) -> Result<Vec<ExternalAgent>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let mut agents = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let mut words = line.split('#').next().unwrap().split_whitespace();
+        let (name, program) = match (words.next(), words.next()) {
+            (None, _) => continue,
+            (Some(name), Some(program)) => (name, program),
+            (Some(_), None) => {
+                return Err(format!(
+                    "{} line {}: expected `name program args...`",
+                    path.display(),
+                    idx + 1
+                ))
+            }
+        };
+        let program = resolve(program)
+            .map_err(|err| format!("{} line {}: {}", path.display(), idx + 1, err))?;
+        agents.push(ExternalAgent {
+            name: name.to_owned(),
+            program,
+            args: words.map(str::to_owned).collect(),
+        });
+    }
+    Ok(agents)
+}
+
+/// Absolute path of `program`, from the current directory if it has more than
+/// one component and otherwise from `PATH`
+fn resolve(program: &str) -> Result<PathBuf, String> {
+    let found = if Path::new(program).components().count() > 1 {
+        fs::canonicalize(program).ok()
+    } else {
+        env::var_os("PATH").and_then(|paths| {
+            env::split_paths(&paths)
+                .map(|dir| dir.join(program))
+                .find(|path| path.is_file())
+        })
+    };
+    match found {
+        Some(path) if path.to_string_lossy().contains(char::is_whitespace) => Err(format!(
+            "the referee can't run `{}`, its path has spaces",
+            path.display()
+        )),
+        Some(path) => Ok(path),
+        None => Err(format!("can't find `{}`", program)),
+    }
+}
+
+/// The absolute path of the profiles file, to pass on to our agent, and the
+/// profile it plays
+fn load_profile(path: &str, name: &str) -> Result<(PathBuf, Profile), String> {
+    // a data file, so not looked for on the `PATH` as programs are
+    let path = fs::canonicalize(path).map_err(|err| format!("can't find `{}`: {}", path, err))?;
+    if path.to_string_lossy().contains(char::is_whitespace) {
+        return Err(format!(
+            "the referee can't pass `{}` to our agent, its path has spaces",
+            path.display()
+        ));
+    }
+    let profile = Profiles::load(&path)?.get(name)?.clone();
+    Ok((path, profile))
 }
 
-impl fmt::Display for Winner {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{:?}", self) }
+/// Build our agent once, so that games neither wait for cargo nor count its
+/// time, and return its path
+fn build_agent() -> Result<PathBuf, String> {
+    let status = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+        .args(["build", "--release", "--bin", "mankalah"])
+        .stdout(Stdio::null())
+        .status()
+        .map_err(|err| format!("couldn't run cargo: {}", err))?;
+    if !status.success() {
+        return Err(format!("building the agent failed: {}", status));
+    }
+    let target =
+        env::var_os("CARGO_TARGET_DIR").map_or_else(|| PathBuf::from("target"), PathBuf::from);
+    resolve(
+        &target
+            .join("release")
+            .join(format!("mankalah{}", env::consts::EXE_SUFFIX))
+            .to_string_lossy(),
+    )
 }
 
+fn fail<T>(err: String) -> T { Error::with_description(&err, ErrorKind::InvalidValue).exit() }
+
+/// One game against an external agent
 #[derive(Debug, Copy, Clone)]
-struct BenchmarkData {
-    winner: Winner,
-    score: i32,
+struct Job<'a> {
     depth: usize,
-    our_time: u32,
-    thier_time: u32,
+    opponent: &'a ExternalAgent,
+    side: Position,
 }
 
 fn main() {
-    for opp in &[
-        "java -jar Test_Agents/error404.jar",
-        "java -jar Test_Agents/JimmyPlayer.jar",
-        "java -jar Test_Agents/Group2Agent.jar",
-    ] {
-        for side in &[false] {
-            println!(
-                "Depth|Winner|Score|Our time|Thier time (we are {}, agaisnt \"{}\")",
-                if *side { "NORTH" } else { "SOUTH" },
-                opp
-            );
-            println!("-----|-----|-----|-----|-----");
+    let cores = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let default_jobs = (cores / CORES_PER_GAME).max(1).to_string();
+    let args = App::new("benchmark")
+        .about("Play our agent against the test agents through the referee")
+        .arg(
+            Arg::with_name("jobs")
+                .long("jobs")
+                .short("j")
+                .help("Games to play at once. Defaults to one per two cores.")
+                .default_value(&default_jobs),
+        )
+        .arg(
+            Arg::with_name("agents")
+                .long("agents")
+                .help("File of opponents, one per line as `name program args...`")
+                .default_value("Test_Agents/agents.txt"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .help("Our agent's profile, played at every depth")
+                .default_value("benchmark"),
+        )
+        .arg(
+            Arg::with_name("profiles")
+                .long("profiles")
+                .help("TOML file of profiles")
+                .default_value(DEFAULT_PROFILES),
+        )
+        .arg(
+            Arg::with_name("ours")
+                .long("ours")
+                .help("Our agent's executable. Defaults to building it with cargo.")
+                .takes_value(true),
+        )
+        .get_matches();
+    let jobs: usize = match args.value_of("jobs").unwrap().parse() {
+        Ok(jobs) if jobs > 0 => jobs,
+        _ => Error::with_description(
+            "--jobs should be a positive number",
+            ErrorKind::InvalidValue,
+        )
+        .exit(),
+    };
+    if jobs * CORES_PER_GAME > cores {
+        eprintln!(
+            "warning: {} games at once need {} cores but there are {}, times will suffer",
+            jobs,
+            jobs * CORES_PER_GAME,
+            cores
+        );
+    }
+
+    let opponents = read_agents(Path::new(args.value_of("agents").unwrap())).unwrap_or_else(fail);
+    let ours = match args.value_of("ours") {
+        Some(path) => resolve(path),
+        None => build_agent(),
+    }
+    .unwrap_or_else(fail);
 
+    let profile_name = args.value_of("profile").unwrap();
+    let (profiles, profile) =
+        load_profile(args.value_of("profiles").unwrap(), profile_name).unwrap_or_else(fail);
+    let weights = profile
+        .weights
+        .unwrap_or_else(|| fail(format!("profile `{}` has no weights", profile_name)));
+    let mut queue = Vec::new();
+    for opponent in &opponents {
+        for &side in &[Position::South] {
             for depth in 1..12 {
-                let BenchmarkData {
-                    score,
+                queue.push(Job {
                     depth,
-                    winner,
-                    our_time,
-                    thier_time,
-                } = benchmark(depth, [1.0, 0.6, 0.0, 0.95, 0.59], opp, *side);
-                println!("{depth}|{winner}|{score}|{our_time}|{thier_time}");
+                    opponent,
+                    side,
+                });
             }
         }
     }
+
+    let revision = results::git_revision();
+    let run = results::now();
+    println!("{}", CSV_HEADER);
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(queue.len()) {
+            let sender = sender.clone();
+            let (queue, next, ours, profiles) = (&queue, &next, &ours, &profiles);
+            scope.spawn(move || {
+                while let Some(&job) = queue.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let us = ExternalAgent {
+                        name: "mankalah".to_owned(),
+                        program: ours.clone(),
+                        args: vec![
+                            "agent".to_owned(),
+                            format!("--profiles={}", profiles.display()),
+                            format!("--profile={}", profile_name),
+                            format!("--depth={}", job.depth),
+                        ],
+                    };
+                    let game = benchmark(&us, job.opponent, job.side);
+                    if sender.send((job, game)).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // in the order they finish
+        for (finished, (job, game)) in receiver.iter().enumerate() {
+            let config = format!(
+                "{} depth={} weights={}",
+                profile.algorithm(),
+                job.depth,
+                weights
+            );
+            let (log, record) = match game {
+                Ok(game) => game,
+                Err(err) => {
+                    eprintln!("{} against {}: {}", config, job.opponent.name, err);
+                    continue;
+                }
+            };
+            let result = GameResult {
+                // an aborted match is lost by whoever broke the rules,
+                // whatever the seeds say
+                winner: log.winner,
+                our_nodes: None,
+                their_nodes: None,
+                ..GameResult::from_record(
+                    &revision,
+                    run,
+                    &config,
+                    &job.opponent.name,
+                    job.side,
+                    &record,
+                )
+            };
+            println!("{}", result);
+            results::append_history(Path::new(HISTORY_DIR), &[result]).unwrap();
+            eprintln!("{}/{} games", finished + 1, queue.len());
+        }
+    });
 }
 
-fn benchmark(depth: usize, weights: [f32; 5], opponent: &str, is_north: bool) -> BenchmarkData {
-    let us: &str = &format!(
-        "cargo run --release --bin mankalah -- --search=alpha-beta --depth={} --weights {}",
-        depth,
-        weights
-            .iter()
-            .map(|w| format!("{:?}", w))
-            .collect::<Vec<_>>()
-            .join(" ")
-    );
-    let them = opponent;
-
-    let (south, north) = if is_north { (them, us) } else { (us, them) };
+/// Play one game through the referee, `side` being the side we start on
+fn benchmark(
+    us: &ExternalAgent,
+    them: &ExternalAgent,
+    side: Position,
+) -> Result<(RefereeLog, GameRecord), String> {
+    let (south, north) = match side {
+        Position::South => (us, them),
+        Position::North => (them, us),
+    };
     let output = Command::new("java")
+        .arg("-XX:ActiveProcessorCount=1")
         .arg("-jar")
         .arg("ManKalah.jar")
-        .arg(south)
-        .arg(north)
+        .arg(south.command())
+        .arg(north.command())
         .output()
-        .unwrap();
+        .map_err(|err| format!("couldn't run the referee: {}", err))?;
 
-    let stderr = String::from_utf8(output.stderr).unwrap();
-    let stderr = stderr.lines().collect::<Vec<_>>();
-    let len = stderr.len();
-
-    let winner_score_str = stderr[len - 5];
-    let winner_score: i32 = winner_score_str["SCORE: ".len()..].parse().unwrap();
-
-    let winner_str = stderr[len - 6];
-    println!("{} -- {}",winner_str, south);
-    let (winner, score) = if winner_str.starts_with("DRAW") {
-        (Winner::Draw, 0)
-    } else if winner_str.starts_with("WINNER: Player 2") {
-        (
-            Winner::North,
-            if is_north {
-                winner_score
-            } else {
-                -winner_score
-            },
-        )
-    } else if winner_str.starts_with("WINNER: Player 1") {
-        (
-            Winner::South,
-            if !is_north {
-                winner_score
-            } else {
-                -winner_score
-            },
-        )
-    } else {
-        unreachable!()
-    };
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let log: RefereeLog = stderr.parse()?;
+    let record = log.record()?;
+    Ok((log, record))
+}
 
-    let north_str = stderr[len - 3];
-    let south_str = stderr[len - 2];
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    let north_words: Vec<_> = north_str.split_whitespace().collect();
-    let south_words: Vec<_> = south_str.split_whitespace().collect();
-    let north_len = north_words.len();
-    let south_len = south_words.len();
-    let north_time: u32 = north_words[north_len - 4].parse().unwrap();
-    let south_time: u32 = south_words[south_len - 4].parse().unwrap();
+    #[test]
+    fn agents_file() {
+        let path = env::temp_dir().join("mankalah_benchmark_agents.txt");
+        fs::write(
+            &path,
+            "# opponents\n\nold sh -c true # comment\njvm java -jar old.jar\n",
+        )
+        .unwrap();
+        let agents = read_agents(&path);
+        fs::write(&path, "lonely\n").unwrap();
+        let lonely = read_agents(&path);
+        fs::write(&path, "missing no-such-program-anywhere\n").unwrap();
+        let missing = read_agents(&path);
+        fs::remove_file(path).unwrap();
 
-    let (our_time, thier_time) = if is_north {
-        (north_time, south_time)
-    } else {
-        (south_time, north_time)
-    };
+        let agents = agents.unwrap();
+        assert_eq!(agents[0].name, "old");
+        assert!(agents[0].program.is_absolute());
+        assert_eq!(agents[0].args, ["-c", "true"]);
+        assert!(agents[0].command().ends_with("sh -c true"));
+        assert!(agents[1]
+            .command()
+            .ends_with("java -XX:ActiveProcessorCount=1 -jar old.jar"));
+        assert!(lonely.unwrap_err().contains("line 1"));
+        assert!(missing.unwrap_err().contains("can't find"));
+    }
 
-    BenchmarkData {
-        winner,
-        score,
-        depth,
-        our_time,
-        thier_time,
+    #[test]
+    fn default_profile() {
+        let (path, profile) = load_profile(DEFAULT_PROFILES, "benchmark").unwrap();
+        assert!(path.is_absolute());
+        let weights = "score=1.0 capture=0.6 chaining=0.95 hoarding=0.59";
+        assert_eq!(profile.weights, Some(weights.parse().unwrap()));
+        assert!(load_profile("no-such-profiles.toml", "benchmark")
+            .unwrap_err()
+            .contains("can't find"));
     }
 }
@@ -3,11 +3,10 @@ use std::{
     ops::{Index, IndexMut, Not},
 };
 
-use crate::heuristics::{weighted_heuristic, Weights, Score};
+use crate::heuristics::{weighted_heuristic, FixedWeights, Score};
 pub type Nat = u8;
 pub const PITS_PER_PLAYER: usize = 7;
 pub const TOTAL_PITS: usize = 2 * (PITS_PER_PLAYER + 1);
-use ordered_float::OrderedFloat;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct PlayerState {
@@ -44,6 +43,22 @@ pub struct BoardState {
     pub south: PlayerState,
 }
 
+/// Same format as the board state in the protocol's `CHANGE` message
+impl fmt::Display for BoardState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let north = &self.north;
+        let south = &self.south;
+        for n in north.pits.iter() {
+            write!(f, "{},", n)?;
+        }
+        write!(f, "{},", north.score)?;
+        for n in south.pits.iter() {
+            write!(f, "{},", n)?;
+        }
+        write!(f, "{}", south.score)
+    }
+}
+
 impl Not for Position {
     type Output = Position;
 
@@ -72,15 +87,6 @@ pub enum PlayerMove {
     Swap,
 }
 
-impl fmt::Display for PlayerMove {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            PlayerMove::Move { n } => write!(f, "MOVE;{}\n", n + 1),
-            PlayerMove::Swap => write!(f, "SWAP\n"),
-        }
-    }
-}
-
 impl Index<Position> for BoardState {
     type Output = PlayerState;
 
@@ -246,9 +252,12 @@ impl BoardState {
                 }
                 (_, _) => !position,
             },
+            // The players change sides, but `Position` names a player rather
+            // than a side of the board, so swap the board instead. The player
+            // who made the first move (now on the other side) moves next.
             PlayerMove::Swap => {
                 std::mem::swap(&mut self.north, &mut self.south);
-                position
+                !position
             }
         };
         if first_move {
@@ -297,7 +306,7 @@ impl BoardState {
     }
 
     pub fn child_boards_sorted_by_heuristics<'a>(
-      &self, child_boards: &'a mut Children, position: Position, first_move: bool, weights: Weights
+      &self, child_boards: &'a mut Children, position: Position, first_move: bool, weights: FixedWeights
     ) -> impl Iterator<Item = ChildBoard> + 'a {
       let mut child_boards_iter = self.child_boards(position, first_move);
 
@@ -312,9 +321,9 @@ impl BoardState {
 
       let eval_child = |child: &Option<ChildBoard>| {
         if let Some(child) = child {
-          weighted_heuristic(weights, &child.1)
+          weighted_heuristic(weights, &child.1, child.2)
         } else {
-          OrderedFloat(-f32::INFINITY)
+          Score::MIN
         }
       };
 
@@ -331,20 +340,20 @@ impl BoardState {
       child_boards.iter().filter_map(|child| *child)
     }
 
-    /// Return Some(score) if board state is terminal
+    /// Return Some(margin) if board state is terminal, where margin is South's
+    /// final number of seeds minus North's.
     /// Else None
-    pub fn is_terminal(&self, pos: Position) -> Option<Score> {
+    pub fn is_terminal(&self, pos: Position) -> Option<i32> {
         match self[pos].moves_iter().next() {
             None => {
-                let our_score = self[pos].score as i8;
-                let mut opp_score = self[!pos].score as i8;
-                opp_score += self[!pos].pits.iter().sum::<u8>() as i8;
+                let our_score = self[pos].score as i32;
+                let mut opp_score = self[!pos].score as i32;
+                opp_score += self[!pos].pits.iter().map(|&n| n as i32).sum::<i32>();
                 let (p1_score, p2_score) = match pos {
                     Position::South => (our_score, opp_score),
                     Position::North => (opp_score, our_score),
                 };
-                // * 1000 for win/lose nodes so we don't confuse with heuristics
-                Some(OrderedFloat((p1_score - p2_score) as f32 * 1000.0))
+                Some(p1_score - p2_score)
             }
             Some(_) => None,
         }
@@ -518,6 +527,43 @@ mod test {
         );
     }
 
+    #[test]
+    fn swap_gives_the_turn_back_to_the_first_player() {
+        let (board_state, position, first_move) =
+            BoardState::default().do_move(PlayerMove::Move { n: 0 }, Position::South, true);
+        assert_eq!((position, first_move), (Position::North, true));
+
+        assert_eq!(
+            board_state.do_move(PlayerMove::Swap, Position::North, first_move),
+            (
+                BoardState {
+                    north: board_state.south,
+                    south: board_state.north,
+                },
+                Position::South,
+                false
+            )
+        );
+    }
+
+    #[test]
+    fn display_board_state() {
+        let board_state = BoardState {
+            north: PlayerState {
+                pits: [1, 2, 3, 4, 5, 6, 7],
+                score: 99,
+            },
+            south: PlayerState {
+                pits: [7, 6, 5, 4, 3, 2, 1],
+                score: 0,
+            },
+        };
+        assert_eq!(
+            board_state.to_string(),
+            "1,2,3,4,5,6,7,99,7,6,5,4,3,2,1,0"
+        );
+    }
+
     #[test]
     fn test_pie_rule() {
         let player_state = PlayerState::default();
@@ -595,20 +641,21 @@ mod test {
                 PlayerMove::Move { n: 5 },
             ]
         );
+    }
 
-        fn wrap_around() {
-            let player_state = PlayerState {
-                score: 0,
-                pits: [0, 0, 4, 0, 2, 8, 0],
-            };
-            assert_eq!(
-                player_state.moves_iter().collect::<Vec<PlayerMove>>(),
-                vec![
-                    PlayerMove::Move { n: 2 },
-                    PlayerMove::Move { n: 4 },
-                    PlayerMove::Move { n: 5 },
-                ]
-            );
-        }
+    #[test]
+    fn wrap_around() {
+        let player_state = PlayerState {
+            score: 0,
+            pits: [0, 0, 4, 0, 2, 8, 0],
+        };
+        assert_eq!(
+            player_state.moves_iter().collect::<Vec<PlayerMove>>(),
+            vec![
+                PlayerMove::Move { n: 2 },
+                PlayerMove::Move { n: 4 },
+                PlayerMove::Move { n: 5 },
+            ]
+        );
     }
 }
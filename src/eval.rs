@@ -1,13 +1,127 @@
 use crate::{
-    board::{BoardState, PlayerMove, Position, Children},
-    heuristics::{weighted_heuristic, Score, Weights},
+    board::{BoardState, PlayerMove, Position},
+    heuristics::{fixed_weights, terminal_score, weighted_heuristic, FixedWeights, Score, Weights},
+    nn::Network,
+    tt::{score_from_tt, score_to_tt, Bound, Entry, Key, TranspositionTable},
+};
+use std::{
+    cmp, fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
-use ordered_float::OrderedFloat;
-use std::cmp;
 
 type Evaluation = (Option<PlayerMove>, Score);
+
+/// Bigger than any score a search can return
+const INFINITY: Score = Score::MAX;
+
+/// Counters for a single call to `Evaluator::eval`
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    /// Positions visited, including leaves
+    pub nodes: u64,
+    /// Positions whose score was taken from the transposition table
+    pub tt_hits: u64,
+    /// Zero-window searches made by MTD(f)
+    pub passes: u32,
+}
+
+impl SearchStats {
+    pub fn merge(&mut self, other: &Self) {
+        self.nodes += other.nodes;
+        self.tt_hits += other.tt_hits;
+        self.passes += other.passes;
+    }
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "nodes = {}, tt_hits = {}, passes = {}",
+            self.nodes, self.tt_hits, self.passes
+        )
+    }
+}
+
+/// State that outlives a single search: the transposition table is kept
+/// between moves, the stats are reset at the start of every search.
+#[derive(Debug, Clone, Default)]
+pub struct Search {
+    pub tt: TranspositionTable,
+    pub stats: SearchStats,
+    /// Evaluates leaves instead of the weighted heuristics, if set
+    pub network: Option<Arc<Network>>,
+    /// Once this is set searches give up, returning a meaningless result and
+    /// leaving the transposition table as it was. Lets another thread stop a
+    /// search that has no limit of its own, e.g. when pondering.
+    pub stop: Option<Arc<AtomicBool>>,
+    /// `leaves[ply]` is the position (and the player to move in it) at the end
+    /// of the best line found so far from the node being searched at `ply`
+    leaves: Vec<(BoardState, Position)>,
+    root_ply: usize,
+}
+
+impl Search {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn with_tt_entries(n_entries: usize) -> Self {
+        Self {
+            tt: TranspositionTable::new(n_entries),
+            stats: SearchStats::default(),
+            network: None,
+            stop: None,
+            leaves: Vec::new(),
+            root_ply: 0,
+        }
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+
+    /// The position at the end of the principal variation of the last search:
+    /// the leaf whose evaluation became the root's score. The line stops early
+    /// where the search took a score from the transposition table.
+    pub fn principal_leaf(&self) -> Option<(BoardState, Position)> {
+        self.leaves.get(self.root_ply).copied()
+    }
+
+    /// Score of a leaf that is not the end of the game
+    pub fn leaf_score(&self, weights: FixedWeights, board: &BoardState, pos: Position) -> Score {
+        match &self.network {
+            Some(network) => network.evaluate(board, pos),
+            None => weighted_heuristic(weights, board, pos),
+        }
+    }
+
+    fn begin(&mut self, root_ply: usize) {
+        self.stats = SearchStats::default();
+        self.root_ply = root_ply;
+        self.leaves.clear();
+    }
+
+    /// The best line from `ply` ends at `board`, with `pos` to move
+    fn set_leaf(&mut self, ply: usize, board: BoardState, pos: Position) {
+        if self.leaves.len() <= ply {
+            self.leaves.resize(ply + 1, (board, pos));
+        }
+        self.leaves[ply] = (board, pos);
+    }
+
+    /// The child just searched from `ply` is the new best move, so its line is
+    /// now the best line from `ply`
+    fn promote_leaf(&mut self, ply: usize) { self.leaves[ply] = self.leaves[ply + 1]; }
+}
+
 pub trait Evaluator {
     fn eval(
+        search: &mut Search,
         board: BoardState,
         pos: Position,
         depth: usize,
@@ -17,11 +131,71 @@ pub trait Evaluator {
     ) -> Evaluation;
 }
 
+/// Choice of `Evaluator` made at runtime, e.g. from the command line
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Algorithm {
+    MiniMax,
+    AlphaBeta,
+    MtdF,
+}
+
+impl Algorithm {
+    pub const NAMES: [&'static str; 3] = ["minimax", "alpha-beta", "mtd-f"];
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn eval(
+        self,
+        search: &mut Search,
+        board: BoardState,
+        pos: Position,
+        depth: usize,
+        first_move: bool,
+        max_depth: usize,
+        weights: Weights,
+    ) -> Evaluation {
+        let eval = match self {
+            Self::MiniMax => MiniMax::eval,
+            Self::AlphaBeta => AlphaBeta::eval,
+            Self::MtdF => MtdF::eval,
+        };
+        eval(search, board, pos, depth, first_move, max_depth, weights)
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "minimax" => Ok(Self::MiniMax),
+            "alpha-beta" => Ok(Self::AlphaBeta),
+            "mtd-f" => Ok(Self::MtdF),
+            _ => Err(format!(
+                "unknown search `{}` (expected one of {})",
+                s,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::MiniMax => Self::NAMES[0],
+            Self::AlphaBeta => Self::NAMES[1],
+            Self::MtdF => Self::NAMES[2],
+        };
+        write!(f, "{}", name)
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum MiniMax {}
 
 impl Evaluator for MiniMax {
     fn eval(
+        search: &mut Search,
         board: BoardState,
         pos: Position,
         depth: usize,
@@ -29,41 +203,55 @@ impl Evaluator for MiniMax {
         max_depth: usize,
         weights: Weights,
     ) -> Evaluation {
-        minimax(board, pos, depth, first_move, max_depth, weights)
+        search.begin(depth);
+        let weights = fixed_weights(weights);
+        minimax(search, board, pos, depth, first_move, max_depth, weights)
     }
 }
 
 fn minimax(
+    search: &mut Search,
     board: BoardState,
     position: Position,
     depth: usize,
     first_move: bool,
     max_depth: usize,
-    weights: Weights,
+    weights: FixedWeights,
 ) -> Evaluation {
-    if let Some(payoff) = board.is_terminal(position) {
-        (None, payoff)
+    search.stats.nodes += 1;
+    if search.stopped() {
+        return (None, 0);
+    }
+    if let Some(margin) = board.is_terminal(position) {
+        search.set_leaf(depth, board, position);
+        (None, terminal_score(margin, depth))
     } else if depth >= max_depth {
-        (None, weighted_heuristic(weights, &board))
+        search.set_leaf(depth, board, position);
+        (None, search.leaf_score(weights, &board, position))
     } else {
-        let iter = board.child_boards(position, first_move).map(
-            |(the_move, board, child_position, next_first_move)| {
-                let (_, score) = minimax(
-                    board,
-                    child_position,
-                    depth + 1,
-                    next_first_move,
-                    max_depth,
-                    weights,
-                );
-                (Some(the_move), score)
-            },
-        );
-
-        match position {
-            Position::South => iter.max_by_key(|&(_, score)| score).unwrap(), // player 1
-            Position::North => iter.min_by_key(|&(_, score)| score).unwrap(), // player 2
+        let mut best: Evaluation = (None, 0);
+        for (the_move, board, child_position, next_first_move) in
+            board.child_boards(position, first_move)
+        {
+            let (_, score) = minimax(
+                search,
+                board,
+                child_position,
+                depth + 1,
+                next_first_move,
+                max_depth,
+                weights,
+            );
+            let is_best = match position {
+                Position::South => score >= best.1, // player 1
+                Position::North => score < best.1,  // player 2
+            };
+            if best.0.is_none() || is_best {
+                best = (Some(the_move), score);
+                search.promote_leaf(depth);
+            }
         }
+        best
     }
 }
 
@@ -72,6 +260,7 @@ pub enum AlphaBeta {}
 
 impl Evaluator for AlphaBeta {
     fn eval(
+        search: &mut Search,
         board: BoardState,
         pos: Position,
         depth: usize,
@@ -79,15 +268,19 @@ impl Evaluator for AlphaBeta {
         max_depth: usize,
         weights: Weights,
     ) -> Evaluation {
-        let alpha = OrderedFloat(-f32::INFINITY);
-        let beta = OrderedFloat(f32::INFINITY);
+        let alpha = -INFINITY;
+        let beta = INFINITY;
+        search.begin(depth);
+        let weights = fixed_weights(weights);
         alpha_beta(
-            board, depth, alpha, beta, pos, first_move, max_depth, weights,
+            search, board, depth, alpha, beta, pos, first_move, max_depth, weights,
         )
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn alpha_beta(
+    search: &mut Search,
     board: BoardState,
     depth: usize,
     mut alpha: Score,
@@ -95,7 +288,7 @@ fn alpha_beta(
     pos: Position,
     first_move: bool,
     max_depth: usize,
-    weights: Weights,
+    weights: FixedWeights,
 ) -> Evaluation {
     log::debug!(
         "{:depth$}alpha = {alpha}, beta = {beta}, pos = {pos}, first_move = {first_move}, \
@@ -111,16 +304,23 @@ fn alpha_beta(
         first_move = first_move,
         max_depth = max_depth
     );
-    if let Some(score) = board.is_terminal(pos) {
+    search.stats.nodes += 1;
+    if search.stopped() {
+        return (None, 0);
+    }
+    if let Some(margin) = board.is_terminal(pos) {
+        let score = terminal_score(margin, depth);
         log::debug!(
             "{:depth$}board is terminal: score = {score}",
             "",
             depth = depth * 2,
             score = score
         );
+        search.set_leaf(depth, board, pos);
         (None, score)
     } else if depth >= max_depth {
-        let score = weighted_heuristic(weights, &board);
+        let score = search.leaf_score(weights, &board, pos);
+        search.set_leaf(depth, board, pos);
         log::debug!(
             "{:depth$}max depth exceeded, using heuristics: score = {score}",
             "",
@@ -129,10 +329,9 @@ fn alpha_beta(
         );
         (None, score)
     } else {
-        // let childre: Children = [None; 8];
         match pos {
             Position::South => {
-                let mut score = OrderedFloat(-f32::INFINITY);
+                let mut score = -INFINITY;
                 let mut value = score;
                 let mut best_move: Option<PlayerMove> = None;
                 for (the_move, child, next_pos, next_fist_move) in
@@ -146,6 +345,7 @@ fn alpha_beta(
                     );
 
                     let (_, child_score) = alpha_beta(
+                        search,
                         child,
                         depth + 1,
                         alpha,
@@ -159,6 +359,7 @@ fn alpha_beta(
                     if child_score > score {
                         score = child_score;
                         best_move = Some(the_move);
+                        search.promote_leaf(depth);
                     }
 
                     value = cmp::max(value, score);
@@ -186,7 +387,7 @@ fn alpha_beta(
                 (best_move, value)
             }
             Position::North => {
-                let mut score = OrderedFloat(f32::INFINITY);
+                let mut score = INFINITY;
                 let mut value = score;
                 let mut best_move: Option<PlayerMove> = None;
                 for (the_move, child, next_pos, next_first_move) in
@@ -200,6 +401,7 @@ fn alpha_beta(
                         the_move = the_move
                     );
                     let (_, child_score) = alpha_beta(
+                        search,
                         child,
                         depth + 1,
                         alpha,
@@ -213,6 +415,7 @@ fn alpha_beta(
                     if child_score < score {
                         score = child_score;
                         best_move = Some(the_move);
+                        search.promote_leaf(depth);
                     }
 
                     value = cmp::min(value, score);
@@ -249,3 +452,348 @@ fn alpha_beta(
         }
     }
 }
+
+/// MTD(f): converges on the minimax value with a sequence of zero-window
+/// searches, relying on the transposition table to avoid re-searching the
+/// parts of the tree already proven by earlier passes.
+#[derive(Debug, Copy, Clone)]
+pub enum MtdF {}
+
+impl Evaluator for MtdF {
+    fn eval(
+        search: &mut Search,
+        board: BoardState,
+        pos: Position,
+        depth: usize,
+        first_move: bool,
+        max_depth: usize,
+        weights: Weights,
+    ) -> Evaluation {
+        search.begin(depth);
+        let weights = fixed_weights(weights);
+        let mut guess = 0;
+        let mut lower = -INFINITY;
+        let mut upper = INFINITY;
+        let mut best_move = None;
+        let mut best_leaf = None;
+
+        while lower < upper && !search.stopped() {
+            let beta = if guess == lower { guess + 1 } else { guess };
+            let (the_move, score) = alpha_beta_with_memory(
+                search,
+                board,
+                depth,
+                beta - 1,
+                beta,
+                pos,
+                first_move,
+                max_depth,
+                weights,
+            );
+            search.stats.passes += 1;
+            log::debug!(
+                "mtd(f) pass {}: window = ({}, {}), score = {}",
+                search.stats.passes,
+                beta - 1,
+                beta,
+                score
+            );
+
+            let failed_high = score >= beta;
+            // only a pass that proves a bound in the mover's favour also proves
+            // its move is the best one
+            let proves_move = match pos {
+                Position::South => failed_high,
+                Position::North => !failed_high,
+            };
+            if proves_move || best_move.is_none() {
+                best_move = the_move;
+                best_leaf = search.principal_leaf();
+            }
+
+            if failed_high {
+                lower = score;
+            } else {
+                upper = score;
+            }
+            guess = score;
+        }
+        if let Some((leaf, leaf_pos)) = best_leaf {
+            search.set_leaf(depth, leaf, leaf_pos);
+        }
+        (best_move, guess)
+    }
+}
+
+/// Fail-soft alpha-beta that stores the result of every node in the
+/// transposition table, and tries the stored best move first
+#[allow(clippy::too_many_arguments)]
+fn alpha_beta_with_memory(
+    search: &mut Search,
+    board: BoardState,
+    depth: usize,
+    mut alpha: Score,
+    mut beta: Score,
+    pos: Position,
+    first_move: bool,
+    max_depth: usize,
+    weights: FixedWeights,
+) -> Evaluation {
+    search.stats.nodes += 1;
+    if search.stopped() {
+        return (None, 0);
+    }
+    if let Some(margin) = board.is_terminal(pos) {
+        search.set_leaf(depth, board, pos);
+        return (None, terminal_score(margin, depth));
+    }
+    if depth >= max_depth {
+        search.set_leaf(depth, board, pos);
+        return (None, search.leaf_score(weights, &board, pos));
+    }
+
+    let key = Key {
+        board,
+        pos,
+        first_move,
+    };
+    let remaining = max_depth - depth;
+    let tt_entry = search.tt.probe(&key);
+    if let Some(entry) = tt_entry {
+        if entry.depth >= remaining {
+            let score = score_from_tt(entry.score, depth);
+            let cutoff = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if cutoff {
+                search.stats.tt_hits += 1;
+                search.set_leaf(depth, board, pos);
+                return (entry.best_move, score);
+            }
+        }
+    }
+    let tt_move = tt_entry.and_then(|entry| entry.best_move);
+
+    let (orig_alpha, orig_beta) = (alpha, beta);
+    let mut children: Vec<_> = board.child_boards(pos, first_move).collect();
+    if let Some(idx) = children.iter().position(|child| Some(child.0) == tt_move) {
+        children.swap(0, idx);
+    }
+
+    let mut best_move = None;
+    let mut value = match pos {
+        Position::South => -INFINITY,
+        Position::North => INFINITY,
+    };
+    for (the_move, child, next_pos, next_first_move) in children {
+        let (_, child_score) = alpha_beta_with_memory(
+            search,
+            child,
+            depth + 1,
+            alpha,
+            beta,
+            next_pos,
+            next_first_move,
+            max_depth,
+            weights,
+        );
+        match pos {
+            Position::South => {
+                if best_move.is_none() || child_score > value {
+                    value = child_score;
+                    best_move = Some(the_move);
+                    search.promote_leaf(depth);
+                }
+                alpha = cmp::max(alpha, value);
+            }
+            Position::North => {
+                if best_move.is_none() || child_score < value {
+                    value = child_score;
+                    best_move = Some(the_move);
+                    search.promote_leaf(depth);
+                }
+                beta = cmp::min(beta, value);
+            }
+        }
+        if alpha >= beta {
+            break;
+        }
+    }
+    if search.stopped() {
+        return (None, 0);
+    }
+
+    let bound = if value <= orig_alpha {
+        Bound::Upper
+    } else if value >= orig_beta {
+        Bound::Lower
+    } else {
+        Bound::Exact
+    };
+    search.tt.store(Entry {
+        key,
+        depth: remaining,
+        score: score_to_tt(value, depth),
+        bound,
+        best_move,
+    });
+    (best_move, value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{board::PlayerState, heuristics::is_decisive};
+
+    fn weights() -> Weights {
+        "score=1.0 capture=0.6 chaining=0.95 hoarding=0.59"
+            .parse()
+            .unwrap()
+    }
+
+    fn positions() -> Vec<(BoardState, Position, bool)> {
+        vec![
+            (BoardState::default(), Position::South, true),
+            (
+                BoardState::default()
+                    .do_move(PlayerMove::Move { n: 3 }, Position::South, true)
+                    .0,
+                Position::North,
+                true,
+            ),
+            (
+                BoardState {
+                    north: PlayerState {
+                        pits: [6, 5, 0, 3, 3, 1, 0],
+                        score: 7,
+                    },
+                    south: PlayerState {
+                        score: 27,
+                        pits: [0, 3, 18, 3, 0, 2, 20],
+                    },
+                },
+                Position::North,
+                false,
+            ),
+            (
+                BoardState {
+                    north: PlayerState {
+                        pits: [0, 3, 3, 0, 0, 0, 1],
+                        score: 30,
+                    },
+                    south: PlayerState {
+                        pits: [2, 0, 3, 0, 0, 0, 4],
+                        score: 50,
+                    },
+                },
+                Position::South,
+                false,
+            ),
+        ]
+    }
+
+    #[track_caller]
+    fn test_same_score<E: Evaluator>(max_depth: usize) {
+        for (board, pos, first_move) in positions() {
+            let (_, expected) = MiniMax::eval(
+                &mut Search::with_tt_entries(1),
+                board,
+                pos,
+                0,
+                first_move,
+                max_depth,
+                weights(),
+            );
+            let mut search = Search::with_tt_entries(1 << 12);
+            let (best_move, got) =
+                E::eval(&mut search, board, pos, 0, first_move, max_depth, weights());
+            assert_eq!(got, expected, "{board:?} {pos}");
+
+            // the chosen move must actually achieve the score
+            let (child, next_pos, next_first_move) =
+                board.do_move(best_move.unwrap(), pos, first_move);
+            let (_, child_score) = MiniMax::eval(
+                &mut Search::with_tt_entries(1),
+                child,
+                next_pos,
+                1,
+                next_first_move,
+                max_depth,
+                weights(),
+            );
+            assert_eq!(child_score, expected, "{board:?} {pos}");
+        }
+    }
+
+    #[test]
+    fn alpha_beta_matches_minimax() { test_same_score::<AlphaBeta>(5); }
+
+    #[test]
+    fn mtdf_matches_minimax() {
+        for depth in 1..=5 {
+            test_same_score::<MtdF>(depth);
+        }
+    }
+
+    #[track_caller]
+    fn test_principal_leaf<E: Evaluator>(max_depth: usize) {
+        let weights = weights();
+        for (board, pos, first_move) in positions() {
+            let mut search = Search::with_tt_entries(1 << 12);
+            let (_, score) = E::eval(&mut search, board, pos, 0, first_move, max_depth, weights);
+            let (leaf, leaf_pos) = search.principal_leaf().unwrap();
+            if !is_decisive(score) {
+                assert_eq!(
+                    weighted_heuristic(fixed_weights(weights), &leaf, leaf_pos),
+                    score,
+                    "{board:?} {pos}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn principal_leaf_has_the_root_score() {
+        test_principal_leaf::<MiniMax>(4);
+        test_principal_leaf::<AlphaBeta>(4);
+        test_principal_leaf::<MtdF>(4);
+    }
+
+    #[test]
+    fn network_evaluates_leaves() {
+        let network = Arc::new(Network::new(8, 0.1, 0));
+        for (board, pos, first_move) in positions() {
+            let mut search = Search::with_tt_entries(1 << 12);
+            search.network = Some(network.clone());
+            let (_, score) = AlphaBeta::eval(&mut search, board, pos, 0, first_move, 3, weights());
+            let (leaf, leaf_pos) = search.principal_leaf().unwrap();
+            if !is_decisive(score) {
+                assert_eq!(network.evaluate(&leaf, leaf_pos), score, "{board:?} {pos}");
+            }
+
+            let mut minimax = Search::with_tt_entries(1);
+            minimax.network = Some(network.clone());
+            let (_, expected) =
+                MiniMax::eval(&mut minimax, board, pos, 0, first_move, 3, weights());
+            assert_eq!(score, expected, "{board:?} {pos}");
+        }
+    }
+
+    #[test]
+    fn mtdf_counts_passes() {
+        let mut search = Search::with_tt_entries(1 << 12);
+        MtdF::eval(
+            &mut search,
+            BoardState::default(),
+            Position::South,
+            0,
+            true,
+            4,
+            weights(),
+        );
+        assert!(search.stats.passes >= 2);
+        assert!(search.stats.nodes > 0);
+    }
+}
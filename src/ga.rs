@@ -0,0 +1,423 @@
+use crate::{
+    game::{play_game, Game, PlayerConfig},
+    heuristics::{Weights, WeightsError, NUM_HEURISTICS},
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{fmt, fs, io, path::Path, str::FromStr};
+
+/// How good a set of weights is: the mean margin of its games
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fitness {
+    /// Play this many other members of the population, on both sides
+    SelfPlay { opponents: usize },
+    /// Play every one of these weights, on both sides
+    Reference(Vec<Weights>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GaConfig {
+    pub population: usize,
+    pub generations: usize,
+    /// The best `elitism` individuals are copied unchanged into the next
+    /// generation
+    pub elitism: usize,
+    /// Chance that a child mixes the genes of two parents rather than copying
+    /// the first
+    pub crossover_rate: f32,
+    /// Chance that each gene of a child is mutated
+    pub mutation_rate: f32,
+    /// Standard deviation of a mutation
+    pub mutation_size: f32,
+    /// Parents are the fittest of this many randomly chosen individuals
+    pub tournament_size: usize,
+    /// Which weights can change. The others stay as in `initial`
+    pub tuned: [bool; NUM_HEURISTICS],
+    /// Weights to start from. The rest of the first generation are random
+    /// mutations of them.
+    pub initial: Weights,
+    pub seed: u64,
+    /// Search used in every game. Its weights are replaced by each
+    /// individual's.
+    pub player: PlayerConfig,
+    pub fitness: Fitness,
+}
+
+impl GaConfig {
+    /// Settings the algorithm can't run with
+    pub fn validate(&self) -> Result<(), String> {
+        if self.population == 0 {
+            return Err("the population needs at least one individual".into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Individual {
+    pub weights: Weights,
+    pub fitness: f32,
+}
+
+/// An evaluated generation, sorted from fittest to least fit, and never empty.
+/// Enough to carry on tuning from, as long as the same `GaConfig` is used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub generation: usize,
+    pub population: Vec<Individual>,
+}
+
+impl Checkpoint {
+    pub fn best(&self) -> &Individual { &self.population[0] }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|err: CheckpointError| {
+                io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+            })
+    }
+
+    /// Write to a temporary file first, so that an interrupted tuning run
+    /// never leaves a truncated checkpoint behind
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, self.to_string())?;
+        fs::rename(&tmp, path)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckpointError {
+    MissingGeneration,
+    EmptyPopulation,
+    BadLine { line: usize, text: String },
+    Weights { line: usize, err: WeightsError },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingGeneration => write!(f, "checkpoint has no `generation` line"),
+            Self::EmptyPopulation => write!(f, "checkpoint has no `individual` lines"),
+            Self::BadLine { line, text } => write!(f, "line {}: cannot parse `{}`", line, text),
+            Self::Weights { line, err } => write!(f, "line {}: {}", line, err),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+/// ```text
+/// generation 12
+/// individual 14.5 score=1 capture=0.6 ...
+/// individual 9.25 score=0.8 capture=0.7 ...
+/// ```
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "generation {}", self.generation)?;
+        for individual in &self.population {
+            writeln!(
+                f,
+                "individual {} {}",
+                individual.fitness, individual.weights
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Checkpoint {
+    type Err = CheckpointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut generation = None;
+        let mut population = Vec::new();
+        for (idx, text) in s.lines().enumerate() {
+            let line = idx + 1;
+            let bad_line = || CheckpointError::BadLine {
+                line,
+                text: text.to_owned(),
+            };
+            let mut words = text.splitn(3, ' ');
+            match (words.next(), words.next(), words.next()) {
+                (Some(""), None, None) => {}
+                (Some("generation"), Some(n), None) => {
+                    generation = Some(n.parse().map_err(|_| bad_line())?);
+                }
+                (Some("individual"), Some(fitness), weights) => {
+                    let fitness = fitness.parse().map_err(|_| bad_line())?;
+                    let weights = weights
+                        .unwrap_or("")
+                        .parse()
+                        .map_err(|err| CheckpointError::Weights { line, err })?;
+                    population.push(Individual { weights, fitness });
+                }
+                _ => return Err(bad_line()),
+            }
+        }
+        let generation = generation.ok_or(CheckpointError::MissingGeneration)?;
+        if population.is_empty() {
+            return Err(CheckpointError::EmptyPopulation);
+        }
+        Ok(Self {
+            generation,
+            population,
+        })
+    }
+}
+
+/// Each generation breeds from its own RNG, so a run resumed from a
+/// checkpoint makes the same choices as one that was never interrupted
+fn generation_rng(seed: u64, generation: usize) -> StdRng {
+    StdRng::seed_from_u64(seed ^ (generation as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+}
+
+/// Sample from a normal distribution with the Box-Muller transform
+fn gaussian(rng: &mut StdRng, std_dev: f32) -> f32 {
+    let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+    let u2: f32 = rng.gen();
+    std_dev * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+fn mutate(config: &GaConfig, rng: &mut StdRng, weights: &mut Weights) {
+    for (gene, &tuned) in weights.0.iter_mut().zip(config.tuned.iter()) {
+        if tuned && rng.gen::<f32>() < config.mutation_rate {
+            *gene += gaussian(rng, config.mutation_size);
+        }
+    }
+}
+
+fn crossover(rng: &mut StdRng, a: &Weights, b: &Weights) -> Weights {
+    let mut child = *a;
+    for (gene, &other) in child.0.iter_mut().zip(b.0.iter()) {
+        if rng.gen() {
+            *gene = other;
+        }
+    }
+    child
+}
+
+fn tournament<'a>(
+    config: &GaConfig,
+    rng: &mut StdRng,
+    population: &'a [Individual],
+) -> &'a Individual {
+    (0..config.tournament_size.max(1))
+        .map(|_| population.choose(rng).unwrap())
+        .max_by(|a, b| a.fitness.partial_cmp(&b.fitness).unwrap())
+        .unwrap()
+}
+
+/// `initial`, then random mutations of it
+fn first_generation(config: &GaConfig) -> Vec<Weights> {
+    let mut rng = generation_rng(config.seed, 0);
+    let mut population = vec![config.initial];
+    while population.len() < config.population {
+        let mut weights = config.initial;
+        for (gene, &tuned) in weights.0.iter_mut().zip(config.tuned.iter()) {
+            if tuned {
+                *gene += gaussian(&mut rng, config.mutation_size);
+            }
+        }
+        population.push(weights);
+    }
+    population
+}
+
+fn next_generation(config: &GaConfig, parents: &Checkpoint) -> Vec<Weights> {
+    let mut rng = generation_rng(config.seed, parents.generation + 1);
+    let population = &parents.population;
+    let mut children: Vec<_> = population
+        .iter()
+        .take(config.elitism)
+        .map(|individual| individual.weights)
+        .collect();
+    while children.len() < config.population {
+        let a = tournament(config, &mut rng, population);
+        let mut child = if rng.gen::<f32>() < config.crossover_rate {
+            let b = tournament(config, &mut rng, population);
+            crossover(&mut rng, &a.weights, &b.weights)
+        } else {
+            a.weights
+        };
+        mutate(config, &mut rng, &mut child);
+        children.push(child);
+    }
+    children
+}
+
+/// Mean margin of `weights` from its own point of view, playing both sides
+/// against each opponent
+fn mean_margin(config: &GaConfig, weights: Weights, opponents: &[Weights]) -> f32 {
+    let us = config.player.with_weights(weights);
+    let mut total = 0;
+    for &opponent in opponents {
+        let them = config.player.with_weights(opponent);
+        total += play_game(&us, &them, Game::default()).margin;
+        total -= play_game(&them, &us, Game::default()).margin;
+    }
+    total as f32 / (2 * opponents.len().max(1)) as f32
+}
+
+fn evaluate(config: &GaConfig, generation: usize, population: Vec<Weights>) -> Checkpoint {
+    let mut rng = generation_rng(config.seed.wrapping_add(1), generation);
+    let mut evaluated: Vec<_> = population
+        .iter()
+        .enumerate()
+        .map(|(idx, &weights)| {
+            let opponents = match &config.fitness {
+                Fitness::Reference(references) => references.clone(),
+                Fitness::SelfPlay { opponents } => {
+                    let others: Vec<_> = population
+                        .iter()
+                        .enumerate()
+                        .filter(|&(other, _)| other != idx)
+                        .map(|(_, &weights)| weights)
+                        .collect();
+                    others
+                        .choose_multiple(&mut rng, *opponents)
+                        .copied()
+                        .collect()
+                }
+            };
+            Individual {
+                weights,
+                fitness: mean_margin(config, weights, &opponents),
+            }
+        })
+        .collect();
+    evaluated.sort_by(|a, b| b.fitness.partial_cmp(&a.fitness).unwrap());
+    Checkpoint {
+        generation,
+        population: evaluated,
+    }
+}
+
+/// Run the genetic algorithm from `resume_from` (or from scratch), calling
+/// `on_generation` with every evaluated generation. Returns the last one.
+pub fn tune(
+    config: &GaConfig,
+    resume_from: Option<Checkpoint>,
+    mut on_generation: impl FnMut(&Checkpoint) -> io::Result<()>,
+) -> io::Result<Checkpoint> {
+    let mut current = match resume_from {
+        Some(checkpoint) => checkpoint,
+        None => {
+            let checkpoint = evaluate(config, 0, first_generation(config));
+            on_generation(&checkpoint)?;
+            checkpoint
+        }
+    };
+    while current.generation + 1 < config.generations {
+        let children = next_generation(config, &current);
+        current = evaluate(config, current.generation + 1, children);
+        on_generation(&current)?;
+    }
+    Ok(current)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::eval::Algorithm;
+
+    fn config() -> GaConfig {
+        let initial: Weights = "score=1 capture=0.5".parse().unwrap();
+        let mut tuned = [false; NUM_HEURISTICS];
+        tuned[0] = true;
+        tuned[1] = true;
+        GaConfig {
+            population: 4,
+            generations: 3,
+            elitism: 1,
+            crossover_rate: 0.7,
+            mutation_rate: 0.5,
+            mutation_size: 0.3,
+            tournament_size: 2,
+            tuned,
+            initial,
+            seed: 7,
+            player: PlayerConfig::new(Algorithm::AlphaBeta, 1, initial),
+            fitness: Fitness::SelfPlay { opponents: 2 },
+        }
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let checkpoint = Checkpoint {
+            generation: 12,
+            population: vec![
+                Individual {
+                    weights: "score=1 capture=0.6".parse().unwrap(),
+                    fitness: 14.5,
+                },
+                Individual {
+                    weights: "score=0.8 hoarding=-0.25".parse().unwrap(),
+                    fitness: -3.25,
+                },
+            ],
+        };
+        assert_eq!(checkpoint.to_string().parse(), Ok(checkpoint));
+        assert_eq!(
+            "individual 1 score=1".parse::<Checkpoint>(),
+            Err(CheckpointError::MissingGeneration)
+        );
+        assert_eq!(
+            "generation 1\n".parse::<Checkpoint>(),
+            Err(CheckpointError::EmptyPopulation)
+        );
+        assert_eq!(
+            "generation 1\nindividual 1 foo=1".parse::<Checkpoint>(),
+            Err(CheckpointError::Weights {
+                line: 2,
+                err: WeightsError::UnknownHeuristic("foo".into())
+            })
+        );
+    }
+
+    #[test]
+    fn empty_populations_are_invalid() {
+        assert_eq!(config().validate(), Ok(()));
+        let empty = GaConfig {
+            population: 0,
+            ..config()
+        };
+        assert!(empty.validate().is_err());
+    }
+
+    #[test]
+    fn only_tuned_weights_change() {
+        let config = config();
+        let last = tune(&config, None, |_| Ok(())).unwrap();
+        assert_eq!(last.generation, 2);
+        assert_eq!(last.population.len(), 4);
+        for individual in &last.population {
+            for (idx, (&gene, &initial)) in individual
+                .weights
+                .0
+                .iter()
+                .zip(config.initial.0.iter())
+                .enumerate()
+            {
+                if !config.tuned[idx] {
+                    assert_eq!(gene, initial);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn resuming_gives_the_same_result() {
+        let config = config();
+        let mut generations = Vec::new();
+        let uninterrupted = tune(&config, None, |checkpoint| {
+            generations.push(checkpoint.clone());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(generations.len(), 3);
+
+        let resumed = tune(&config, Some(generations[0].clone()), |_| Ok(())).unwrap();
+        assert_eq!(resumed, uninterrupted);
+    }
+}
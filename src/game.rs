@@ -0,0 +1,502 @@
+use crate::{
+    board::{BoardState, Nat, PlayerMove, Position, PITS_PER_PLAYER},
+    eval::{Algorithm, Search, SearchStats},
+    heuristics::{Weights, MAX_PLY},
+    nn::Network,
+};
+use rand::{seq::IteratorRandom, Rng};
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Entries in the transposition table of each player in an in-process game.
+/// Much smaller than the agent's, since tuning plays thousands of short games.
+pub const PLAYER_TT_ENTRIES: usize = 1 << 16;
+
+/// Everything needed to replay a game from a position: the board, whose turn
+/// it is and whether North can still use the pie rule
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Game {
+    pub board: BoardState,
+    pub to_move: Position,
+    pub first_move: bool,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self {
+            board: BoardState::default(),
+            to_move: Position::South,
+            first_move: true,
+        }
+    }
+}
+
+impl Game {
+    pub fn legal_moves(&self) -> impl Iterator<Item = PlayerMove> + '_ {
+        self.board
+            .child_boards(self.to_move, self.first_move)
+            .map(|child| child.0)
+    }
+
+    pub fn play(&mut self, the_move: PlayerMove) {
+        let (board, to_move, first_move) =
+            self.board.do_move(the_move, self.to_move, self.first_move);
+        self.board = board;
+        self.to_move = to_move;
+        self.first_move = first_move;
+    }
+
+    /// South's final seeds minus North's, if the game is over
+    pub fn result(&self) -> Option<i32> { self.board.is_terminal(self.to_move) }
+
+    /// Whether the referee has ended the game. It doesn't wait until the
+    /// player to move has no seeds, as we do, but stops as soon as either
+    /// side is empty.
+    pub fn referee_over(&self) -> bool {
+        [self.board.north, self.board.south]
+            .iter()
+            .any(|side| side.pits.iter().all(|&seeds| seeds == 0))
+    }
+
+    /// The board as the referee leaves it at the end, with the seeds left in
+    /// each side's pits moved to its store
+    pub fn swept(&self) -> BoardState {
+        let mut board = self.board;
+        for side in [&mut board.north, &mut board.south] {
+            side.score += side.pits.iter().sum::<Nat>();
+            side.pits = [0; PITS_PER_PLAYER];
+        }
+        board
+    }
+}
+
+/// How long an in-process player searches each move for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Limit {
+    /// Search this many plies
+    Depth(usize),
+    /// Deepen one ply at a time until half of this has gone. The iteration
+    /// after that would usually take longer than the rest.
+    Time(Duration),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Depth(depth) => write!(f, "depth {}", depth),
+            Self::Time(time) => write!(f, "{}ms", time.as_millis()),
+        }
+    }
+}
+
+/// How an in-process player chooses its moves
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerConfig {
+    pub algorithm: Algorithm,
+    pub limit: Limit,
+    pub weights: Weights,
+    /// Evaluates leaves instead of `weights`, if set
+    pub network: Option<Arc<Network>>,
+}
+
+impl PlayerConfig {
+    pub fn new(algorithm: Algorithm, depth: usize, weights: Weights) -> Self {
+        Self {
+            algorithm,
+            limit: Limit::Depth(depth),
+            weights,
+            network: None,
+        }
+    }
+
+    /// The same player, with different weights
+    pub fn with_weights(&self, weights: Weights) -> Self {
+        Self {
+            weights,
+            ..self.clone()
+        }
+    }
+
+    fn search(&self, search: &mut Search, game: &Game, depth: usize) -> Option<PlayerMove> {
+        let (chosen_move, _) = self.algorithm.eval(
+            search,
+            game.board,
+            game.to_move,
+            0,
+            game.first_move,
+            depth,
+            self.weights,
+        );
+        chosen_move
+    }
+
+    pub fn choose_move(&self, search: &mut Search, game: &Game) -> PlayerMove {
+        self.choose_move_after(search, game, None)
+    }
+
+    /// Like `choose_move`, given the depth and best move of a search of
+    /// `game` that has already finished, e.g. while pondering. A deep enough
+    /// search is used as it is, a time limit deepens from the next depth.
+    /// Afterwards `search.stats` counts every depth searched.
+    pub fn choose_move_after(
+        &self,
+        search: &mut Search,
+        game: &Game,
+        finished: Option<(usize, PlayerMove)>,
+    ) -> PlayerMove {
+        search.network = self.network.clone();
+        search.stats = SearchStats::default();
+        let (mut depth, mut chosen_move) = match finished {
+            Some((depth, chosen_move)) => (depth + 1, Some(chosen_move)),
+            None => (1, None),
+        };
+        match self.limit {
+            Limit::Depth(max_depth) if depth > max_depth => chosen_move.unwrap(),
+            Limit::Depth(max_depth) => self.search(search, game, max_depth).unwrap(),
+            Limit::Time(time) => {
+                let start = Instant::now();
+                let mut total = SearchStats::default();
+                while depth <= MAX_PLY {
+                    chosen_move = self.search(search, game, depth);
+                    total.merge(&search.stats);
+                    if start.elapsed() >= time / 2 {
+                        break;
+                    }
+                    depth += 1;
+                }
+                search.stats = total;
+                chosen_move.unwrap()
+            }
+        }
+    }
+
+    /// The best move in `game` by a search `depth` plies deep
+    pub fn search_to(&self, search: &mut Search, game: &Game, depth: usize) -> Option<PlayerMove> {
+        search.network = self.network.clone();
+        self.search(search, game, depth)
+    }
+}
+
+/// Effort one player put into a game
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PlayerStats {
+    pub time: Duration,
+    pub nodes: u64,
+}
+
+/// A finished game: where it started, every move played and the result
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameRecord {
+    pub start: Game,
+    pub moves: Vec<PlayerMove>,
+    /// South's final seeds minus North's
+    pub margin: i32,
+    pub south: PlayerStats,
+    pub north: PlayerStats,
+}
+
+impl GameRecord {
+    /// Every position of the game, from `start` to the final position
+    pub fn positions(&self) -> impl Iterator<Item = Game> + '_ {
+        let mut game = self.start;
+        std::iter::once(game).chain(self.moves.iter().map(move |&the_move| {
+            game.play(the_move);
+            game
+        }))
+    }
+}
+
+/// Play a game between two in-process players, starting from `start`
+pub fn play_game(south: &PlayerConfig, north: &PlayerConfig, start: Game) -> GameRecord {
+    let mut south_search = Search::with_tt_entries(PLAYER_TT_ENTRIES);
+    let mut north_search = Search::with_tt_entries(PLAYER_TT_ENTRIES);
+    let mut game = start;
+    let mut moves = Vec::new();
+    let mut south_stats = PlayerStats::default();
+    let mut north_stats = PlayerStats::default();
+    loop {
+        if let Some(margin) = game.result() {
+            return GameRecord {
+                start,
+                moves,
+                margin,
+                south: south_stats,
+                north: north_stats,
+            };
+        }
+        let (player, search, stats) = match game.to_move {
+            Position::South => (south, &mut south_search, &mut south_stats),
+            Position::North => (north, &mut north_search, &mut north_stats),
+        };
+        let started = Instant::now();
+        let the_move = player.choose_move(search, &game);
+        stats.time += started.elapsed();
+        stats.nodes += search.stats.nodes;
+        game.play(the_move);
+        moves.push(the_move);
+    }
+}
+
+/// Positions `depth` plies after `game`, counting each line of play
+/// separately. Checks move generation against known counts.
+pub fn perft(game: Game, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    game.legal_moves()
+        .map(|the_move| {
+            let mut child = game;
+            child.play(the_move);
+            perft(child, depth - 1)
+        })
+        .sum()
+}
+
+/// Random games `random_opening` plays before giving up
+pub const OPENING_TRIES: usize = 1000;
+
+/// A position reached by `plies` random moves from the start, which is not
+/// already over. Gives in-process games between deterministic players some
+/// variety. An error if none of `OPENING_TRIES` random games lasts that long.
+pub fn random_opening(rng: &mut impl Rng, plies: usize) -> Result<Game, String> {
+    'retry: for _ in 0..OPENING_TRIES {
+        let mut game = Game::default();
+        for _ in 0..plies {
+            match game.legal_moves().choose(rng) {
+                Some(the_move) => game.play(the_move),
+                None => continue 'retry,
+            }
+        }
+        if game.result().is_none() {
+            return Ok(game);
+        }
+    }
+    Err(format!(
+        "none of {} random games lasted {} plies",
+        OPENING_TRIES, plies
+    ))
+}
+
+/// Results of a match, from the first player's point of view
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct MatchResult {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// Sum of the first player's final seeds minus the second player's
+    pub total_margin: i64,
+}
+
+impl MatchResult {
+    pub fn games(&self) -> u32 { self.wins + self.draws + self.losses }
+
+    pub fn mean_margin(&self) -> f64 { self.total_margin as f64 / self.games().max(1) as f64 }
+
+    /// Add a game the first player finished `margin` seeds ahead in
+    pub fn add(&mut self, margin: i32) {
+        match margin {
+            0 => self.draws += 1,
+            m if m > 0 => self.wins += 1,
+            _ => self.losses += 1,
+        }
+        self.total_margin += margin as i64;
+    }
+
+    /// The same games, from the second player's point of view
+    pub fn reversed(&self) -> Self {
+        Self {
+            wins: self.losses,
+            draws: self.draws,
+            losses: self.wins,
+            total_margin: -self.total_margin,
+        }
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.wins += other.wins;
+        self.draws += other.draws;
+        self.losses += other.losses;
+        self.total_margin += other.total_margin;
+    }
+}
+
+impl fmt::Display for MatchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "W/D/L {}/{}/{}, mean margin {:+.2}",
+            self.wins,
+            self.draws,
+            self.losses,
+            self.mean_margin()
+        )
+    }
+}
+
+/// Play `a` against `b` from each of `openings`, once with each colour
+pub fn play_match(a: &PlayerConfig, b: &PlayerConfig, openings: &[Game]) -> MatchResult {
+    play_match_with(a, b, openings, |_, _| {})
+}
+
+/// Like `play_match`, calling `on_game` with the side `a` played and the
+/// record of every game
+pub fn play_match_with(
+    a: &PlayerConfig,
+    b: &PlayerConfig,
+    openings: &[Game],
+    mut on_game: impl FnMut(Position, &GameRecord),
+) -> MatchResult {
+    let mut result = MatchResult::default();
+    for &opening in openings {
+        let record = play_game(a, b, opening);
+        result.add(record.margin);
+        on_game(Position::South, &record);
+        let record = play_game(b, a, opening);
+        result.add(-record.margin);
+        on_game(Position::North, &record);
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn player(depth: usize) -> PlayerConfig {
+        PlayerConfig::new(
+            Algorithm::AlphaBeta,
+            depth,
+            "score=1.0 capture=0.6 chaining=0.95 hoarding=0.59"
+                .parse()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn perft_counts_swap_once() {
+        assert_eq!(perft(Game::default(), 0), 1);
+        assert_eq!(perft(Game::default(), 1), 7);
+        // North can answer any first move with one of 7 pits or the swap
+        assert_eq!(perft(Game::default(), 2), 7 * 8);
+        let no_pie_rule = Game {
+            first_move: false,
+            ..Game::default()
+        };
+        assert!(perft(no_pie_rule, 2) < 7 * 8);
+    }
+
+    #[test]
+    fn finished_searches_are_not_repeated() {
+        let game = random_opening(&mut rand::rngs::StdRng::seed_from_u64(2), 6).unwrap();
+        let mut search = Search::new();
+        let best = player(3).choose_move(&mut search, &game);
+        // a depth 5 search that already finished is enough for depth 3
+        let pondered = PlayerMove::Move { n: 6 };
+        assert_eq!(
+            player(3).choose_move_after(&mut search, &game, Some((5, pondered))),
+            pondered
+        );
+        assert_eq!(search.stats, SearchStats::default());
+        assert_eq!(
+            player(3).choose_move_after(&mut search, &game, Some((2, pondered))),
+            best
+        );
+        assert!(search.stats.nodes > 0);
+    }
+
+    #[test]
+    fn timed_searches_count_every_depth() {
+        let game = random_opening(&mut rand::rngs::StdRng::seed_from_u64(3), 6).unwrap();
+        let timed = PlayerConfig {
+            limit: Limit::Time(Duration::from_millis(40)),
+            ..player(1)
+        };
+        let mut search = Search::new();
+        timed.choose_move(&mut search, &game);
+        let nodes = search.stats.nodes;
+
+        // the same depths one at a time, as iterative deepening searched them
+        let mut replay = Search::new();
+        let mut total = 0;
+        let mut depths = 0;
+        while total < nodes {
+            depths += 1;
+            timed.search_to(&mut replay, &game, depths);
+            total += replay.stats.nodes;
+        }
+        assert_eq!(total, nodes);
+        assert!(depths > 1);
+    }
+
+    #[test]
+    fn games_end_with_every_seed_in_a_store() {
+        let record = play_game(&player(2), &player(3), Game::default());
+        let last = record.positions().last().unwrap();
+        assert_eq!(last.result(), Some(record.margin));
+        assert_eq!(record.positions().count(), record.moves.len() + 1);
+
+        let board = last.board;
+        let stores = board.north.score as i32 + board.south.score as i32;
+        let pits: i32 = [board.north, board.south]
+            .iter()
+            .flat_map(|side| side.pits.iter())
+            .map(|&n| n as i32)
+            .sum();
+        // the player who still has seeds keeps them
+        assert_eq!(stores + pits, 98);
+        assert_eq!(
+            record.margin,
+            board.south.score as i32 - board.north.score as i32
+                + match last.to_move {
+                    Position::South => -pits,
+                    Position::North => pits,
+                }
+        );
+    }
+
+    #[test]
+    fn games_between_fixed_depth_players_are_deterministic() {
+        let a = play_game(&player(3), &player(2), Game::default());
+        let b = play_game(&player(3), &player(2), Game::default());
+        assert_eq!(a.moves, b.moves);
+        assert_eq!(a.margin, b.margin);
+        assert_eq!(a.south.nodes, b.south.nodes);
+        assert!(a.south.nodes > 0 && a.north.nodes > 0);
+    }
+
+    #[test]
+    fn matches_play_both_colours() {
+        let openings = [Game::default()];
+        let result = play_match(&player(2), &player(2), &openings);
+        assert_eq!(result.games(), 2);
+        // the same player on both sides wins as one colour and loses as the other
+        assert_eq!(result.total_margin, 0);
+        assert_eq!(result.wins, result.losses);
+    }
+
+    #[test]
+    fn random_openings_are_not_over() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        for _ in 0..20 {
+            let game = random_opening(&mut rng, 8).unwrap();
+            assert_eq!(game.result(), None);
+            assert_ne!(game, Game::default());
+        }
+        assert!(random_opening(&mut rng, 300).is_err());
+    }
+
+    #[test]
+    fn only_north_can_swap_and_only_once() {
+        let mut game = Game::default();
+        assert!(!game.legal_moves().any(|m| m == PlayerMove::Swap));
+        game.play(PlayerMove::Move { n: 0 });
+        assert!(game.legal_moves().any(|m| m == PlayerMove::Swap));
+        game.play(PlayerMove::Swap);
+        assert_eq!(game.to_move, Position::South);
+        assert!(!game.legal_moves().any(|m| m == PlayerMove::Swap));
+    }
+}
@@ -6,7 +6,13 @@ use std::{convert::TryInto, str::FromStr};
 peg::parser! {
     pub grammar ProtocolGrammar() for str {
         rule Nat() -> Nat
-            = n: $(['0'..='9']+) {Nat::from_str(n).unwrap()}
+            = n: $(['0'..='9']+) {? Nat::from_str(n).or(Err("a number of seeds")) }
+
+        // player moves are 1-based
+        rule Hole() -> Nat
+            = n: Nat() {?
+                if (1..=PITS_PER_PLAYER as Nat).contains(&n) { Ok(n - 1) } else { Err("a hole from 1 to 7") }
+            }
 
         /// Messages sent from the engine to the agent
         pub rule EngineMessage() -> EngineMessage
@@ -16,7 +22,7 @@ peg::parser! {
             = "START" ";" pos: Position() "\n"
             {EngineMessage::NewMatch{pos}}
 
-        rule Position() -> Position
+        pub rule Position() -> Position
             = "North" {Position::North}
             / "South" {Position::South}
 
@@ -25,18 +31,19 @@ peg::parser! {
             {EngineMessage::StateChange{player_move, state, turn}}
 
         rule PlayerMove() -> PlayerMove
-            // player moves are 1-based
-            = n: Nat() {PlayerMove::Move{n: n - 1}}
+            = n: Hole() {PlayerMove::Move{n}}
             / "SWAP" {PlayerMove::Swap}
 
-        rule State() -> BoardState
+        /// Board in the format used by the `CHANGE` message: North's pits and
+        /// store, then South's
+        pub rule State() -> BoardState
             = north: PlayerState() "," south: PlayerState()
             {BoardState{north, south}}
 
         rule PlayerState() -> PlayerState
             = pits: Nat() ** <{PITS_PER_PLAYER}> ","
               "," score: Nat()
-              {PlayerState{pits: pits.try_into().unwrap(), score}}
+              {? pits.try_into().map(|pits| PlayerState{pits, score}).or(Err("a pit for each hole")) }
 
         rule Turn() -> Turn
             = "YOU" {Turn::You}
@@ -46,6 +53,11 @@ peg::parser! {
         rule GameOver() -> EngineMessage
             = "END" "\n"
             {EngineMessage::GameOver}
+
+        /// Messages sent from the agent to the engine
+        pub rule AgentMessage() -> AgentMessage
+            = "MOVE" ";" n: Hole() "\n" {AgentMessage::Move{n}}
+            / "SWAP" "\n" {AgentMessage::Swap}
     }
 }
 
@@ -143,6 +155,65 @@ mod test {
         )
     }
 
+    #[test]
+    fn state_round_trip() {
+        let state = BoardState::default()
+            .do_move(PlayerMove::Move { n: 2 }, Position::South, true)
+            .0;
+        assert_eq!(ProtocolGrammar::State(&state.to_string()), Ok(state));
+    }
+
     #[test]
     fn game_over() { test_engine_message("END\n", Ok(EngineMessage::GameOver)) }
+
+    /// The lines of a corpus in `testdata`, as messages
+    fn corpus(text: &str) -> impl Iterator<Item = String> + '_ {
+        text.lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| format!("{}\n", line))
+    }
+
+    #[test]
+    fn valid_corpus() {
+        for message in corpus(include_str!("../testdata/protocol_valid.txt")) {
+            let parsed = ProtocolGrammar::EngineMessage(&message)
+                .unwrap_or_else(|err| panic!("{:?}: {}", message, err));
+            assert_eq!(parsed.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn invalid_corpus() {
+        for message in corpus(include_str!("../testdata/protocol_invalid.txt")) {
+            assert!(
+                ProtocolGrammar::EngineMessage(&message).is_err(),
+                "{:?} parsed",
+                message
+            );
+        }
+        // a message is only complete with its newline
+        assert!(ProtocolGrammar::EngineMessage("START;South").is_err());
+    }
+
+    #[test]
+    fn agent_messages() {
+        assert_eq!(
+            ProtocolGrammar::AgentMessage("MOVE;7\n"),
+            Ok(AgentMessage::Move { n: 6 })
+        );
+        for invalid in &[
+            "MOVE;0\n",
+            "MOVE;8\n",
+            "MOVE;256\n",
+            "MOVE;\n",
+            "SWAP",
+            "swap\n",
+        ] {
+            assert!(
+                ProtocolGrammar::AgentMessage(invalid).is_err(),
+                "{:?}",
+                invalid
+            );
+        }
+    }
 }
@@ -1,138 +1,568 @@
-use crate::board::{
-    BoardState, FinalLocation, Nat, PlayerMove, Position, PITS_PER_PLAYER, TOTAL_PITS,
-};
-use ordered_float::OrderedFloat;
-
-pub type Score = OrderedFloat<f32>;
-pub const NUM_HEURISTICS: usize = 5;
-pub const HEURISTICS: [fn(&BoardState) -> i8; NUM_HEURISTICS] = [
-    current_score,
-    offensive_capture,
-    defensive_capture,
-    chaining_potential,
-    hoarding,
+use crate::board::{BoardState, FinalLocation, Nat, PlayerMove, Position};
+use std::{fmt, fs, io, path::Path, str::FromStr};
+
+/// Evaluation of a position from South's point of view, in thousandths of a
+/// seed
+pub type Score = i32;
+
+/// Bound on every score, which no forced win reaches
+pub const WIN: Score = 1 << 30;
+pub const LOSS: Score = -WIN;
+pub const DRAW: Score = 0;
+/// No search goes deeper than this
+pub const MAX_PLY: usize = 1024;
+/// Largest margin a forced result is scored by: all the seeds of a game
+pub const MAX_MARGIN: i32 = 98;
+
+/// Forced results score further from `DRAW` than this, heuristic estimates
+/// no further. A win scores `MAX_PLY + 1` more for every seed of its margin
+/// and one less for every ply until it, so the search prefers the biggest
+/// win, then the quickest, and the smallest loss, then the slowest.
+pub const DECISIVE: Score = WIN - (MAX_MARGIN + 1) * (MAX_PLY as Score + 1);
+
+/// South wins by no seeds `ply` plies from the root, the least any win scores
+pub fn win_in(ply: usize) -> Score { DECISIVE + (MAX_PLY - ply) as Score }
+
+/// North wins by no seeds `ply` plies from the root
+pub fn loss_in(ply: usize) -> Score { -win_in(ply) }
+
+pub fn is_decisive(score: Score) -> bool { score.abs() > DECISIVE }
+
+/// Score of a finished game `ply` plies from the root, given South's final
+/// seeds minus North's
+pub fn terminal_score(margin: i32, ply: usize) -> Score {
+    let by = margin.abs().min(MAX_MARGIN) * (MAX_PLY as Score + 1);
+    match margin {
+        0 => DRAW,
+        m if m > 0 => win_in(ply) + by,
+        _ => loss_in(ply) - by,
+    }
+}
+
+/// A term of the evaluation function, measured from South's point of view
+/// (South's value minus North's)
+pub trait Heuristic: Sync {
+    /// Name of the term when giving weights, e.g. `score=1.0`
+    fn name(&self) -> &'static str;
+
+    /// Value of the term for `board` when it is `to_move`'s turn
+    fn evaluate(&self, board: &BoardState, to_move: Position) -> i32;
+
+    /// Value of the term for `child`, reached by playing `the_move` as `pos`
+    /// from `parent` where the term was `parent_value`. Only worth overriding
+    /// when that is cheaper than evaluating `child` from scratch.
+    fn update(
+        &self,
+        _parent: &BoardState,
+        _parent_value: i32,
+        _the_move: PlayerMove,
+        _pos: Position,
+        _child: &BoardState,
+        _child_to_move: Position,
+    ) -> Option<i32> {
+        None
+    }
+
+    fn evaluate_child(
+        &self,
+        parent: &BoardState,
+        parent_value: i32,
+        the_move: PlayerMove,
+        pos: Position,
+        child: &BoardState,
+        child_to_move: Position,
+    ) -> i32 {
+        self.update(parent, parent_value, the_move, pos, child, child_to_move)
+            .unwrap_or_else(|| self.evaluate(child, child_to_move))
+    }
+}
+
+impl fmt::Debug for dyn Heuristic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{}", self.name()) }
+}
+
+/// A heuristic that is just a named function of the board, whoever's turn it
+/// is
+#[derive(Debug, Copy, Clone)]
+pub struct Term {
+    pub name: &'static str,
+    pub eval: fn(&BoardState) -> i32,
+}
+
+impl Heuristic for Term {
+    fn name(&self) -> &'static str { self.name }
+
+    fn evaluate(&self, board: &BoardState, _to_move: Position) -> i32 { (self.eval)(board) }
+}
+
+/// A heuristic that depends on whose turn it is
+#[derive(Debug, Copy, Clone)]
+pub struct SideToMoveTerm {
+    pub name: &'static str,
+    pub eval: fn(&BoardState, Position) -> i32,
+}
+
+impl Heuristic for SideToMoveTerm {
+    fn name(&self) -> &'static str { self.name }
+
+    fn evaluate(&self, board: &BoardState, to_move: Position) -> i32 { (self.eval)(board, to_move) }
+}
+
+/// Stores only change by the seeds sown or captured by a single move, so the
+/// difference can be updated from the parent's
+#[derive(Debug, Copy, Clone)]
+pub struct StoreDifference;
+
+impl Heuristic for StoreDifference {
+    fn name(&self) -> &'static str { "score" }
+
+    fn evaluate(&self, board: &BoardState, _to_move: Position) -> i32 { current_score(board) }
+
+    fn update(
+        &self,
+        parent: &BoardState,
+        parent_value: i32,
+        _the_move: PlayerMove,
+        _pos: Position,
+        child: &BoardState,
+        _child_to_move: Position,
+    ) -> Option<i32> {
+        let gained = |pos: Position| child[pos].score as i32 - parent[pos].score as i32;
+        Some(parent_value + gained(Position::South) - gained(Position::North))
+    }
+}
+
+pub const NUM_HEURISTICS: usize = 10;
+
+/// Every evaluation term, in the order of their weights
+pub static HEURISTICS: [&dyn Heuristic; NUM_HEURISTICS] = [
+    &StoreDifference,
+    &SideToMoveTerm {
+        name: "capture",
+        eval: capture_threat,
+    },
+    &SideToMoveTerm {
+        name: "defence",
+        eval: capture_exposure,
+    },
+    &Term {
+        name: "chaining",
+        eval: chaining_potential,
+    },
+    &Term {
+        name: "hoarding",
+        eval: hoarding,
+    },
+    &Term {
+        name: "mobility",
+        eval: mobility,
+    },
+    &Term {
+        name: "starvation",
+        eval: starvation,
+    },
+    &Term {
+        name: "seeds",
+        eval: seeds_on_side,
+    },
+    &Term {
+        name: "majority",
+        eval: store_majority,
+    },
+    &Term {
+        name: "overflow",
+        eval: overflow,
+    },
 ];
-pub type Weights = [f32; NUM_HEURISTICS];
 
-pub(crate) fn weighted_heuristic(weights: Weights, board: &BoardState) -> Score {
-    let mut score = 0.0;
+/// Index of the heuristic called `name` in `HEURISTICS`
+pub fn heuristic_index(name: &str) -> Option<usize> {
+    HEURISTICS.iter().position(|h| h.name() == name)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WeightsError {
+    UnknownHeuristic(String),
+    InvalidWeight {
+        name: String,
+        value: String,
+    },
+    /// A weight that isn't of the form `name=value`
+    Malformed(String),
+    /// A weight further from zero than `MAX_WEIGHT`
+    OutOfRange {
+        name: String,
+        weight: f32,
+    },
+}
+
+impl fmt::Display for WeightsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownHeuristic(name) => {
+                let known: Vec<_> = HEURISTICS.iter().map(|h| h.name()).collect();
+                write!(
+                    f,
+                    "unknown heuristic `{}` (expected one of {})",
+                    name,
+                    known.join(", ")
+                )
+            }
+            Self::InvalidWeight { name, value } => {
+                write!(f, "weight `{}` for `{}` is not a number", value, name)
+            }
+            Self::OutOfRange { name, weight } => write!(
+                f,
+                "weight {} for `{}` is not between -{} and {}",
+                weight, name, MAX_WEIGHT, MAX_WEIGHT
+            ),
+            Self::Malformed(weight) => {
+                write!(
+                    f,
+                    "expected a weight of the form `name=value`, got `{}`",
+                    weight
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for WeightsError {}
+
+/// Heuristic weights as given on the command line, indexed like `HEURISTICS`.
+/// Written as `name=value` pairs separated by spaces or commas; terms that
+/// aren't mentioned have a weight of zero.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Weights(pub [f32; NUM_HEURISTICS]);
+
+impl Weights {
+    pub fn get(&self, name: &str) -> Option<f32> { heuristic_index(name).map(|idx| self.0[idx]) }
+
+    pub fn set(&mut self, name: &str, weight: f32) -> Result<(), WeightsError> {
+        let idx =
+            heuristic_index(name).ok_or_else(|| WeightsError::UnknownHeuristic(name.to_owned()))?;
+        if !(-MAX_WEIGHT..=MAX_WEIGHT).contains(&weight) {
+            return Err(WeightsError::OutOfRange {
+                name: name.to_owned(),
+                weight,
+            });
+        }
+        self.0[idx] = weight;
+        Ok(())
+    }
+
+    /// `(name, weight)` of every heuristic
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, f32)> + '_ {
+        HEURISTICS
+            .iter()
+            .map(|h| h.name())
+            .zip(self.0.iter().copied())
+    }
+
+    /// Read weights written by `save`, or by hand in the same format
+    pub fn load(path: &Path) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|err: WeightsError| {
+                io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+            })
+    }
+
+    /// Write the weights on one line, as they would be given to `--weights`
+    pub fn save(&self, path: &Path) -> io::Result<()> { fs::write(path, format!("{}\n", self)) }
+}
+
+impl FromStr for Weights {
+    type Err = WeightsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights = Self::default();
+        for pair in s.split(|c: char| c == ',' || c.is_whitespace()) {
+            if pair.is_empty() {
+                continue;
+            }
+            let mut split = pair.splitn(2, '=');
+            let (name, value) = match (split.next(), split.next()) {
+                (Some(name), Some(value)) => (name, value),
+                _ => return Err(WeightsError::Malformed(pair.to_owned())),
+            };
+            let weight = f32::from_str(value).map_err(|_| WeightsError::InvalidWeight {
+                name: name.to_owned(),
+                value: value.to_owned(),
+            })?;
+            weights.set(name, weight)?;
+        }
+        Ok(weights)
+    }
+}
+
+impl fmt::Display for Weights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, (name, weight)) in self.iter().enumerate() {
+            if idx > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}={}", name, weight)?;
+        }
+        Ok(())
+    }
+}
+
+/// Weights in fixed point: `WEIGHT_SCALE` is a weight of 1.0
+pub type FixedWeights = [Score; NUM_HEURISTICS];
+pub const WEIGHT_SCALE: Score = 1000;
+/// Largest weight that can be given, either way
+pub const MAX_WEIGHT: f32 = 1000.0;
+
+/// Weights tuned past `MAX_WEIGHT` are held at it
+pub fn fixed_weights(weights: Weights) -> FixedWeights {
+    let mut fixed = [0; NUM_HEURISTICS];
+    for (fixed, weight) in fixed.iter_mut().zip(weights.0.iter()) {
+        // the cast takes NaN to 0
+        *fixed = (weight.clamp(-MAX_WEIGHT, MAX_WEIGHT) * WEIGHT_SCALE as f32).round() as Score;
+    }
+    fixed
+}
+
+/// A weighted sum of terms as a heuristic estimate, which is never far enough
+/// from a draw to be taken for a forced result
+fn estimate(sum: i64) -> Score {
+    let bound = DECISIVE as i64 - 1;
+    sum.clamp(-bound, bound) as Score
+}
+
+pub fn weighted_heuristic(
+    weights: FixedWeights,
+    board: &BoardState,
+    to_move: Position,
+) -> Score {
+    let mut score = 0;
     for (h, weight) in HEURISTICS.iter().zip(weights.iter()) {
-        if *weight != 0.0_f32 {
-            score += h(board) as f32 * weight;
+        if *weight != 0 {
+            score += h.evaluate(board, to_move) as i64 * *weight as i64;
+        }
+    }
+    estimate(score)
+}
+
+/// Unweighted value of every term for `board`, indexed like `HEURISTICS`.
+/// `weighted_heuristic` is linear in these, which is what makes supervised
+/// tuning of the weights possible.
+pub fn features(board: &BoardState, to_move: Position) -> [i32; NUM_HEURISTICS] {
+    let mut features = [0; NUM_HEURISTICS];
+    for (feature, h) in features.iter_mut().zip(HEURISTICS.iter()) {
+        *feature = h.evaluate(board, to_move);
+    }
+    features
+}
+
+/// How one term contributed to the evaluation of a board
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TermBreakdown {
+    pub name: &'static str,
+    /// Unweighted value of the term
+    pub value: i32,
+    pub weight: f32,
+    /// `value` times the fixed-point weight, as added to the score
+    pub contribution: Score,
+}
+
+/// Every term of `weighted_heuristic` for a single board
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub terms: Vec<TermBreakdown>,
+    pub total: Score,
+}
+
+/// Break the evaluation of `board` with `to_move` to play down into its terms.
+/// Unlike `weighted_heuristic`, terms with a weight of zero are evaluated too.
+pub fn explain(weights: Weights, board: &BoardState, to_move: Position) -> Explanation {
+    let fixed = fixed_weights(weights);
+    let terms: Vec<_> = HEURISTICS
+        .iter()
+        .zip(weights.0.iter().zip(fixed.iter()))
+        .map(|(h, (&weight, &fixed_weight))| {
+            let value = h.evaluate(board, to_move);
+            TermBreakdown {
+                name: h.name(),
+                value,
+                weight,
+                contribution: value.saturating_mul(fixed_weight),
+            }
+        })
+        .collect();
+    let total = estimate(terms.iter().map(|term| term.contribution as i64).sum());
+    Explanation { terms, total }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12}{:>8}{:>10}{:>14}",
+            "term", "value", "weight", "contribution"
+        )?;
+        for term in &self.terms {
+            writeln!(
+                f,
+                "{:<12}{:>8}{:>10}{:>14}",
+                term.name, term.value, term.weight, term.contribution
+            )?;
         }
+        write!(f, "{:<12}{:>32}", "total", self.total)
     }
-    OrderedFloat(score)
 }
 
 /// Difference between mancalas (score)
-fn current_score(board: &BoardState) -> i8 {
-    let south_seeds = board[Position::South].score as i8;
-    let north_seeds = board[Position::North].score as i8;
+fn current_score(board: &BoardState) -> i32 {
+    let south_seeds = board[Position::South].score as i32;
+    let north_seeds = board[Position::North].score as i32;
     south_seeds - north_seeds
 }
 
-/// Offensive Capture: incentivise choosing boards with more capture
-/// opportunites
-fn offensive_capture(board: &BoardState) -> i8 {
-    fn count_captures(board: &BoardState, pos: Position) -> i8 {
-        let mut n_captures = 0;
-        // for (starting_pit, &n_stones) in board[pos]
-        //     .pits
-        //     .iter()
-        //     .enumerate()
-        //     .filter(|(_, &n_stones)| n_stones > 0)
-        // {
-        //     let final_pit = (starting_pit + n_stones as usize) % (TOTAL_PITS - 1);
-        //     if final_pit >= 7 {
-        //       continue;
-        //     }
-        //     if let (Some(&n_landed), Some(&n_opposite)) = (
-        //         board[pos].pits.get(final_pit),
-        //         board[!pos]
-        //             .pits
-        //             .get(BoardState::opposite_pit(final_pit as Nat) as usize),
-        //     ) {
-        //         let n_stones_deposited_in_starting_pit = n_stones as usize /
-        // (TOTAL_PITS - 1);
-
-        //         // player can capture if:
-        //         let a = n_landed == 0; // the stone lands in an empty pit
-        //         let b = final_pit == starting_pit; // or the stone lands in the pit
-        // where he started         let c = n_stones_deposited_in_starting_pit
-        // == 1; // but only when that stone is the first stone deposited in the pit
-        // where he                                                          //
-        // started         let d = n_opposite > 0; // and there are more than 0
-        // stones in the pit opposite
-
-        //         if (a || (b && c)) && d {
-        //             n_captures += n_opposite + 1
-        //         }
-        //     }
-        // }
-        // n_captures as i8
-        for possible_move in board[pos].moves_iter() {
-            if let PlayerMove::Move { n } = possible_move {
-                let mut board = board.clone();
-                let final_location = board.sow_seeds(pos, n);
-                match (pos, final_location) {
-                    (Position::South, FinalLocation::South(n))
-                    | (Position::North, FinalLocation::North(n)) => {
-                        let captured = board[!pos].pits[BoardState::opposite_pit(n) as usize];
-                        if board[pos].pits[n as usize] == 1 && captured > 0 {
-                            n_captures += captured + 1;
-                        }
-                    }
-                    _ => {
-                        continue;
-                    }
+/// Seeds captured by each of `pos`'s moves, including the capturing seed.
+/// Sowing is simulated, so this includes moves that capture by wrapping all
+/// the way around the board into an empty pit, or into the pit they started
+/// from.
+fn captures(board: &BoardState, pos: Position) -> impl Iterator<Item = i32> + '_ {
+    board[pos].moves_iter().map(move |possible_move| {
+        let n = match possible_move {
+            PlayerMove::Move { n } => n,
+            PlayerMove::Swap => unreachable!(),
+        };
+        let mut board = *board;
+        match (pos, board.sow_seeds(pos, n)) {
+            (Position::South, FinalLocation::South(n))
+            | (Position::North, FinalLocation::North(n)) => {
+                let captured = board[!pos].pits[BoardState::opposite_pit(n) as usize];
+                if board[pos].pits[n as usize] == 1 && captured > 0 {
+                    captured as i32 + 1
+                } else {
+                    0
                 }
-            } else {
-                unreachable!();
             }
+            _ => 0,
         }
-        n_captures as i8
-    }
+    })
+}
 
-    let north_captures = count_captures(board, Position::North);
-    let south_captures = count_captures(board, Position::South);
-    south_captures - north_captures
+/// The most seeds `pos` could capture if it were their turn
+fn best_capture(board: &BoardState, pos: Position) -> i32 {
+    captures(board, pos).max().unwrap_or(0)
 }
 
-/// Offensive Capture: incentivise choosing boards with less capture
-/// opportunites for opponent.
-fn defensive_capture(board: &BoardState) -> i8 { -offensive_capture(board) }
+/// Capture threat: the player to move can take their best capture right now
+fn capture_threat(board: &BoardState, to_move: Position) -> i32 {
+    match to_move {
+        Position::South => best_capture(board, Position::South),
+        Position::North => -best_capture(board, Position::North),
+    }
+}
+
+/// Capture exposure: the player to move must defend against the opponent's
+/// best capture, or lose those seeds on the opponent's next turn
+fn capture_exposure(board: &BoardState, to_move: Position) -> i32 {
+    match to_move {
+        Position::South => -best_capture(board, Position::North),
+        Position::North => best_capture(board, Position::South),
+    }
+}
 
 /// Chaining Potential: incentivise moves that repeat your turn.
-fn chaining_potential(board: &BoardState) -> i8 {
-    fn count_chains(board: &BoardState, pos: Position) -> u8 {
+fn chaining_potential(board: &BoardState) -> i32 {
+    fn count_chains(board: &BoardState, pos: Position) -> i32 {
         board
             .child_boards(pos, false)
             .filter(|child| child.2 == pos)
-            .count() as u8
+            .count() as i32
     }
-    let south_chains = count_chains(board, Position::South) as i8;
-    let north_chains = count_chains(board, Position::North) as i8;
+    let south_chains = count_chains(board, Position::South);
+    let north_chains = count_chains(board, Position::North);
     south_chains - north_chains
 }
 
 /// Hoarding Stategy: look to pick boards that maximise the number of seeds in
 /// the 2 pits closest to our mancala.
-fn hoarding(board: &BoardState) -> i8 {
-    let n_south = board[Position::South].pits.iter().rev().take(2).sum::<u8>() as i8;
-    let n_north = board[Position::North].pits.iter().rev().take(2).sum::<u8>() as i8;
+fn hoarding(board: &BoardState) -> i32 {
+    let n_south = board[Position::South].pits.iter().rev().take(2).sum::<u8>() as i32;
+    let n_north = board[Position::North].pits.iter().rev().take(2).sum::<u8>() as i32;
     n_south - n_north
 }
 
+/// Mobility: prefer boards where we have more moves to choose from
+fn mobility(board: &BoardState) -> i32 {
+    let n_south = board[Position::South].moves_iter().count() as i32;
+    let n_north = board[Position::North].moves_iter().count() as i32;
+    n_south - n_north
+}
+
+/// Starvation: an empty pit lets its owner capture the seeds in the pit
+/// opposite, so avoid leaving seeds opposite the opponent's empty pits
+fn starvation(board: &BoardState) -> i32 {
+    fn exposed_seeds(board: &BoardState, pos: Position) -> i32 {
+        board[!pos]
+            .pits
+            .iter()
+            .enumerate()
+            .filter(|(_, &n_stones)| n_stones == 0)
+            .map(|(n, _)| board[pos].pits[BoardState::opposite_pit(n as Nat) as usize] as i32)
+            .sum()
+    }
+    exposed_seeds(board, Position::North) - exposed_seeds(board, Position::South)
+}
+
+/// Seeds on side: when one player runs out of moves the other player keeps
+/// every seed left on their side (see `BoardState::is_terminal`)
+fn seeds_on_side(board: &BoardState) -> i32 {
+    let n_south = board[Position::South].pits.iter().sum::<u8>() as i32;
+    let n_north = board[Position::North].pits.iter().sum::<u8>() as i32;
+    n_south - n_north
+}
+
+/// Store majority: a player with more than half of all the seeds in their
+/// store has won, however the rest of the game goes
+fn store_majority(board: &BoardState) -> i32 {
+    let total: i32 = [Position::South, Position::North]
+        .iter()
+        .map(|&pos| {
+            board[pos].score as i32 + board[pos].pits.iter().map(|&n| n as i32).sum::<i32>()
+        })
+        .sum();
+    let has_majority = |pos: Position| 2 * board[pos].score as i32 > total;
+    has_majority(Position::South) as i32 - has_majority(Position::North) as i32
+}
+
+/// Overflow: seeds that a move would sow onto the opponent's side are given
+/// away, since they can only be played (or kept at the end) by the opponent
+fn overflow(board: &BoardState) -> i32 {
+    fn seeds_given_away(board: &BoardState, pos: Position) -> i32 {
+        let opp_seeds = |board: &BoardState| board[!pos].pits.iter().sum::<u8>() as i32;
+        board[pos]
+            .moves_iter()
+            .map(|possible_move| match possible_move {
+                PlayerMove::Move { n } => {
+                    let mut child = *board;
+                    child.sow_seeds(pos, n);
+                    opp_seeds(&child) - opp_seeds(board)
+                }
+                PlayerMove::Swap => unreachable!(),
+            })
+            .sum()
+    }
+    seeds_given_away(board, Position::North) - seeds_given_away(board, Position::South)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::board::{PlayerState, PITS_PER_PLAYER};
 
+    /// Total seeds South could capture minus total seeds North could capture
     #[track_caller]
     fn test_offensive_capture(
         north: [u8; PITS_PER_PLAYER],
         south: [u8; PITS_PER_PLAYER],
-        expected: i8,
+        expected: i32,
     ) {
         let board = BoardState {
             north: PlayerState {
@@ -144,10 +574,180 @@ mod test {
                 pits: south,
             },
         };
-        let got = offensive_capture(&board);
+        let got = captures(&board, Position::South).sum::<i32>()
+            - captures(&board, Position::North).sum::<i32>();
         assert_eq!(got, expected);
     }
 
+    #[track_caller]
+    fn test_side_to_move_captures(
+        board: BoardState,
+        to_move: Position,
+        expected_threat: i32,
+        expected_exposure: i32,
+    ) {
+        assert_eq!(capture_threat(&board, to_move), expected_threat);
+        assert_eq!(capture_exposure(&board, to_move), expected_exposure);
+    }
+
+    #[test]
+    fn fixed_point_weights() {
+        let weights: Weights = "score=1.0 capture=0.6 chaining=0.95 hoarding=-0.59"
+            .parse()
+            .unwrap();
+        let fixed = fixed_weights(weights);
+        assert_eq!(fixed[heuristic_index("score").unwrap()], 1000);
+        assert_eq!(fixed[heuristic_index("capture").unwrap()], 600);
+        assert_eq!(fixed[heuristic_index("defence").unwrap()], 0);
+        assert_eq!(fixed[heuristic_index("chaining").unwrap()], 950);
+        assert_eq!(fixed[heuristic_index("hoarding").unwrap()], -590);
+    }
+
+    #[test]
+    fn weights_are_bounded() {
+        assert_eq!(
+            "score=1000000".parse::<Weights>(),
+            Err(WeightsError::OutOfRange {
+                name: "score".to_owned(),
+                weight: 1_000_000.0
+            })
+        );
+        assert!("score=NaN".parse::<Weights>().is_err());
+        assert!("score=-1000".parse::<Weights>().is_ok());
+
+        // weights from tuning don't go through `set`
+        let board = BoardState {
+            north: PlayerState {
+                score: 0,
+                pits: [0; PITS_PER_PLAYER],
+            },
+            south: PlayerState {
+                score: 98,
+                pits: [0; PITS_PER_PLAYER],
+            },
+        };
+        let fixed = fixed_weights(Weights([f32::MAX; NUM_HEURISTICS]));
+        assert_eq!(fixed[0], (MAX_WEIGHT * WEIGHT_SCALE as f32) as Score);
+        for &to_move in &[Position::South, Position::North] {
+            assert!(!is_decisive(weighted_heuristic(fixed, &board, to_move)));
+        }
+    }
+
+    fn tuned_weights() -> Weights {
+        let mut weights = Weights::default();
+        weights.set("score", 1.0).unwrap();
+        weights.set("capture", 0.6).unwrap();
+        weights.set("chaining", 0.95).unwrap();
+        weights.set("hoarding", -0.59).unwrap();
+        weights
+    }
+
+    #[test]
+    fn parse_named_weights() {
+        assert_eq!(
+            "score=1.0 capture=0.6 chaining=0.95,hoarding=-0.59".parse(),
+            Ok(tuned_weights())
+        );
+        assert_eq!("".parse(), Ok(Weights::default()));
+        assert_eq!(
+            "score=1 foo=2".parse::<Weights>(),
+            Err(WeightsError::UnknownHeuristic("foo".into()))
+        );
+        assert_eq!(
+            "score=lots".parse::<Weights>(),
+            Err(WeightsError::InvalidWeight {
+                name: "score".into(),
+                value: "lots".into()
+            })
+        );
+        assert_eq!(
+            "score".parse::<Weights>(),
+            Err(WeightsError::Malformed("score".into()))
+        );
+    }
+
+    #[test]
+    fn weights_round_trip() {
+        let weights = tuned_weights();
+        let text = weights.to_string();
+        assert_eq!(
+            text,
+            "score=1 capture=0.6 defence=0 chaining=0.95 hoarding=-0.59 mobility=0 starvation=0 \
+             seeds=0 majority=0 overflow=0"
+        );
+        assert_eq!(text.parse(), Ok(weights));
+
+        let path = std::env::temp_dir().join("mankalah_weights_round_trip.weights");
+        weights.save(&path).unwrap();
+        assert_eq!(Weights::load(&path).unwrap(), weights);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn explain_adds_up_to_weighted_heuristic() {
+        let board = BoardState {
+            north: PlayerState {
+                pits: [6, 5, 0, 3, 3, 1, 0],
+                score: 7,
+            },
+            south: PlayerState {
+                score: 27,
+                pits: [0, 3, 18, 3, 0, 2, 20],
+            },
+        };
+        let weights = tuned_weights();
+        let explanation = explain(weights, &board, Position::North);
+        assert_eq!(
+            explanation.total,
+            weighted_heuristic(fixed_weights(weights), &board, Position::North)
+        );
+        assert_eq!(explanation.terms.len(), NUM_HEURISTICS);
+
+        let score = explanation.terms[heuristic_index("score").unwrap()];
+        assert_eq!(score.value, 20);
+        assert_eq!(score.contribution, 20_000);
+        // unweighted terms are still shown
+        let seeds = explanation.terms[heuristic_index("seeds").unwrap()];
+        assert_eq!(seeds.value, 28);
+        assert_eq!(seeds.contribution, 0);
+    }
+
+    #[test]
+    fn store_difference_updates_incrementally() {
+        let mut board = BoardState::default();
+        let mut pos = Position::South;
+        for &n in &[3, 0, 6, 2, 5] {
+            let the_move = PlayerMove::Move { n };
+            let (child, next_pos, _) = board.do_move(the_move, pos, false);
+            let value = StoreDifference.evaluate(&board, pos);
+            assert_eq!(
+                StoreDifference.evaluate_child(&board, value, the_move, pos, &child, next_pos),
+                StoreDifference.evaluate(&child, next_pos)
+            );
+            board = child;
+            pos = next_pos;
+        }
+    }
+
+    #[test]
+    fn bigger_then_quicker_wins_score_higher() {
+        assert!(terminal_score(1, 3) > terminal_score(1, 5));
+        assert!(terminal_score(-1, 5) > terminal_score(-1, 3));
+        assert!(terminal_score(2, MAX_PLY) > terminal_score(1, 0));
+        assert!(terminal_score(-1, 0) > terminal_score(-2, MAX_PLY));
+        assert!(is_decisive(terminal_score(1, MAX_PLY)));
+        assert!(is_decisive(terminal_score(-1, MAX_PLY)));
+        assert!(!is_decisive(DRAW));
+        assert_eq!(terminal_score(0, 7), DRAW);
+        // margins beyond a game's seeds, from a custom board, are capped
+        assert!(terminal_score(MAX_MARGIN + 50, 0) < WIN);
+        assert_eq!(
+            terminal_score(-MAX_MARGIN - 50, 0),
+            terminal_score(-MAX_MARGIN, 0)
+        );
+        assert!(terminal_score(-MAX_MARGIN, 0) > LOSS);
+    }
+
     #[test]
     fn neither_side_can_capture() { test_offensive_capture([1; 7], [1; 7], 0); }
 
@@ -234,4 +834,141 @@ mod test {
             0,
         );
     }
+
+    fn board(north: [u8; PITS_PER_PLAYER], south: [u8; PITS_PER_PLAYER]) -> BoardState {
+        BoardState {
+            north: PlayerState {
+                score: 0,
+                pits: north,
+            },
+            south: PlayerState {
+                score: 0,
+                pits: south,
+            },
+        }
+    }
+
+    #[track_caller]
+    fn test_heuristic(heuristic: fn(&BoardState) -> i32, board: BoardState, expected: i32) {
+        let got = heuristic(&board);
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn mobility_counts_non_empty_pits() {
+        test_heuristic(mobility, board([1; 7], [1; 7]), 0);
+        test_heuristic(mobility, board([0, 0, 0, 0, 0, 0, 1], [1; 7]), 6);
+        test_heuristic(mobility, board([1; 7], [0, 3, 0, 3, 0, 3, 0]), -4);
+    }
+
+    #[test]
+    fn starvation_counts_seeds_opposite_empty_pits() {
+        test_heuristic(starvation, board([1; 7], [1; 7]), 0);
+        // north's pit 0 is empty, opposite south's pit 6
+        test_heuristic(
+            starvation,
+            board([0, 1, 1, 1, 1, 1, 1], [1, 1, 1, 1, 1, 1, 5]),
+            -5,
+        );
+        // south's pit 2 is empty, opposite north's pit 4
+        test_heuristic(
+            starvation,
+            board([1, 1, 1, 1, 9, 1, 1], [1, 1, 0, 1, 1, 1, 1]),
+            9,
+        );
+        // an empty pit opposite an empty pit exposes nothing
+        test_heuristic(
+            starvation,
+            board([0, 1, 1, 1, 1, 1, 1], [1, 1, 1, 1, 1, 1, 0]),
+            0,
+        );
+    }
+
+    #[test]
+    fn seeds_on_side_counts_pits_not_stores() {
+        test_heuristic(seeds_on_side, board([1; 7], [1; 7]), 0);
+        test_heuristic(seeds_on_side, board([0, 0, 0, 0, 0, 0, 3], [1; 7]), 4);
+        let mut with_stores = board([2; 7], [1; 7]);
+        with_stores.south.score = 50;
+        test_heuristic(seeds_on_side, with_stores, -7);
+    }
+
+    #[test]
+    fn store_majority_needs_more_than_half_the_seeds() {
+        let mut b = BoardState::default();
+        test_heuristic(store_majority, b, 0);
+
+        // 98 seeds in total
+        b.south = PlayerState {
+            score: 49,
+            pits: [0; 7],
+        };
+        b.north = PlayerState {
+            score: 0,
+            pits: [7; 7],
+        };
+        test_heuristic(store_majority, b, 0);
+
+        b.south.score = 50;
+        b.north.pits[0] = 6;
+        test_heuristic(store_majority, b, 1);
+
+        std::mem::swap(&mut b.north, &mut b.south);
+        test_heuristic(store_majority, b, -1);
+    }
+
+    #[test]
+    fn overflow_counts_seeds_sown_onto_the_opponents_side() {
+        // single seeds never leave their own side
+        test_heuristic(overflow, board([1; 7], [1; 7]), 0);
+        // 3 seeds from south's last pit: 1 in the store, 2 to north
+        test_heuristic(overflow, board([0; 7], [0, 0, 0, 0, 0, 0, 3]), -2);
+        // 10 seeds from north's pit 4: 2 in north's pits, 1 in the store, 7 to south
+        test_heuristic(overflow, board([0, 0, 0, 0, 10, 0, 0], [0; 7]), 7);
+    }
+
+    #[test]
+    fn north_threatens_capture() {
+        // north can capture 1 + 1 by playing its pit 0 into its empty pit 1
+        let b = board([1, 0, 1, 1, 1, 1, 1], [1; 7]);
+        test_side_to_move_captures(b, Position::North, -2, 0);
+        test_side_to_move_captures(b, Position::South, 0, -2);
+    }
+
+    #[test]
+    fn south_threatens_capture() {
+        let b = board([1, 1, 1, 1, 1, 5, 1], [1, 1, 1, 1, 1, 1, 0]);
+        // south's pit 5 into its empty pit 6 takes north's pit 0
+        test_side_to_move_captures(b, Position::South, 2, 0);
+        test_side_to_move_captures(b, Position::North, 0, 2);
+    }
+
+    #[test]
+    fn only_the_best_capture_counts() {
+        // north can capture 1 + 1 from pit 0 or 1 + 4 from pit 2
+        let b = board([1, 0, 1, 0, 1, 1, 1], [1, 1, 1, 4, 1, 1, 1]);
+        test_side_to_move_captures(b, Position::North, -5, 0);
+        test_side_to_move_captures(b, Position::South, 0, -5);
+    }
+
+    #[test]
+    fn capture_threat_and_exposure_are_independent() {
+        let b = board(
+            [1, 1, 1, 1, 1, 1, 0], // north
+            [1, 0, 1, 1, 1, 1, 1], // south
+        );
+        test_side_to_move_captures(b, Position::South, 2, -2);
+        test_side_to_move_captures(b, Position::North, -2, 2);
+    }
+
+    #[test]
+    fn capture_by_wrapping_around_for_the_side_to_move() {
+        let b = board([0, 1, 1, 1, 1, 10, 1], [1; 7]);
+        test_side_to_move_captures(b, Position::North, -3, 0);
+        test_side_to_move_captures(b, Position::South, 0, -3);
+
+        // landing back in the starting pit after a full lap
+        let b = board([0, 1, 1, 1, 1, 15, 1], [1; 7]);
+        test_side_to_move_captures(b, Position::North, -3, 0);
+    }
 }
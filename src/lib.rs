@@ -5,7 +5,6 @@
     clippy::all
 )]
 #![deny(bare_trait_objects)]
-#![feature(format_args_capture)]
 
 // TODOS:
 // [x] more heuristics
@@ -15,9 +14,21 @@
 // [ ] heuristic weights
 
 pub mod agent;
-pub mod benchmark;
 pub mod board;
 pub mod eval;
+pub mod ga;
+pub mod game;
 pub mod grammar;
 pub mod heuristics;
+pub mod net;
+pub mod nn;
+pub mod openings;
+pub mod profile;
 pub mod protocol;
+pub mod referee;
+pub mod results;
+pub mod stats;
+pub mod td;
+pub mod texel;
+pub mod tournament;
+pub mod tt;
@@ -1,11 +1,248 @@
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, Error, ErrorKind, SubCommand};
 use flexi_logger::Logger;
+use log::{info, warn};
 use mankalah::{
-    agent::Agent,
-    eval::{AlphaBeta, MiniMax},
-    heuristics::Weights,
+    agent::{Agent, AgentError},
+    board::{BoardState, PlayerMove, Position},
+    eval::{Algorithm, Search},
+    ga::{self, Checkpoint, Fitness, GaConfig},
+    game::{self, play_match, random_opening, Game, Limit, PlayerConfig},
+    grammar::ProtocolGrammar,
+    heuristics::{explain, heuristic_index, Weights, MAX_PLY, NUM_HEURISTICS},
+    net,
+    nn::{self, Network},
+    openings::{self, SuiteConfig},
+    profile::{Eval, Profile, Profiles, DEFAULT_PROFILES},
+    protocol::AgentMessage,
+    results::{self, GameResult},
+    stats::{EloEstimate, Sprt, SprtDecision},
+    td::{Learner, TdConfig},
+    texel::{self, TexelConfig},
+    tournament::{self, Entrant, Format},
 };
-use std::{convert::TryInto, str::FromStr};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, BufRead, Write},
+    net::TcpListener,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+fn search_arg() -> Arg<'static, 'static> {
+    Arg::with_name("search")
+        .long("search")
+        .possible_values(&["minimax", "alpha-beta", "mtd-f"])
+        .default_value("alpha-beta")
+}
+
+fn weights_arg() -> Arg<'static, 'static> {
+    Arg::with_name("weight")
+        .long("weights")
+        .help(
+            "Heuristic weights as name=value pairs, e.g. `score=1.0 capture=0.6`, or @FILE to \
+             read them from a file",
+        )
+        .takes_value(true)
+        .multiple(true)
+        .required(true)
+}
+
+fn eval_arg() -> Arg<'static, 'static> {
+    Arg::with_name("eval")
+        .long("eval")
+        .help("Evaluate leaves with the weighted heuristics or with a network trained by `train`")
+        .possible_values(&["linear", "mlp"])
+        .default_value("linear")
+}
+
+fn network_arg() -> Arg<'static, 'static> {
+    Arg::with_name("network")
+        .long("network")
+        .help("Network file for --eval mlp")
+        .takes_value(true)
+        .required_if("eval", "mlp")
+}
+
+/// How long a player searches each move for
+fn limit_args(depth_help: &'static str) -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("depth")
+            .long("depth")
+            .help(depth_help)
+            .takes_value(true),
+        Arg::with_name("time")
+            .long("time")
+            .help("Milliseconds per move: deepen the search until half of them have gone")
+            .takes_value(true)
+            .conflicts_with("depth"),
+    ]
+}
+
+/// Everything `parse_profile` reads: the search, evaluator, weights, limit
+/// and table size, and a profile to take whatever isn't given from
+fn player_args(depth_help: &'static str) -> Vec<Arg<'static, 'static>> {
+    let mut args = vec![
+        search_arg(),
+        eval_arg(),
+        network_arg(),
+        weights_arg().required_unless("profile"),
+        Arg::with_name("tt-entries")
+            .long("tt-entries")
+            .help("Entries in the transposition table [default: 1048576]")
+            .takes_value(true),
+        Arg::with_name("profile")
+            .long("profile")
+            .help("Play as this profile, overridden by any of the options above")
+            .takes_value(true),
+        Arg::with_name("profiles")
+            .long("profiles")
+            .help("TOML file of profiles, as [profile.NAME] tables")
+            .default_value(DEFAULT_PROFILES),
+    ];
+    args.extend(limit_args(depth_help));
+    args
+}
+
+fn rules_arg() -> Arg<'static, 'static> {
+    Arg::with_name("no-pie-rule")
+        .long("no-pie-rule")
+        .help("Play without the pie rule, so North can't swap sides after South's first move")
+}
+
+fn history_arg() -> Arg<'static, 'static> {
+    Arg::with_name("history")
+        .long("history")
+        .help("Directory of results, one CSV and one JSON lines file per git revision")
+        .default_value("history")
+}
+
+fn parse_weights(args: &ArgMatches<'_>) -> Weights {
+    let weights: Vec<String> = args
+        .values_of("weight")
+        .unwrap()
+        .map(|value| match value.strip_prefix('@') {
+            Some(path) => fs::read_to_string(path)
+                .unwrap_or_else(|err| io_error("cannot read weights", Path::new(path), err)),
+            None => value.to_owned(),
+        })
+        .collect();
+    weights.join(" ").parse().unwrap_or_else(|err| {
+        Error::with_description(&format!("{}", err), ErrorKind::InvalidValue).exit()
+    })
+}
+
+fn parse_limit(args: &ArgMatches<'_>) -> Option<Limit> {
+    if args.is_present("time") {
+        Some(Limit::Time(Duration::from_millis(parse_arg(args, "time"))))
+    } else if args.is_present("depth") {
+        Some(Limit::Depth(parse_depth(args)))
+    } else {
+        None
+    }
+}
+
+/// The options given by `player_args`: those on the command line, then those
+/// of the profile, then `defaults`
+fn parse_profile(args: &ArgMatches<'_>, defaults: Profile) -> Profile {
+    let given = |name| args.occurrences_of(name) > 0;
+    let command_line = Profile {
+        algorithm: if given("search") {
+            Some(parse_arg(args, "search"))
+        } else {
+            None
+        },
+        eval: match args.value_of("eval") {
+            Some("mlp") => Some(Eval::Network(args.value_of("network").unwrap().into())),
+            Some(_) if given("eval") => Some(Eval::Linear),
+            _ => None,
+        },
+        limit: parse_limit(args),
+        weights: if given("weight") {
+            Some(parse_weights(args))
+        } else {
+            None
+        },
+        tt_entries: if given("tt-entries") {
+            Some(parse_arg(args, "tt-entries"))
+        } else {
+            None
+        },
+        ponder: if given("ponder") { Some(true) } else { None },
+        book: None,
+    };
+    let profile = match args.value_of("profile") {
+        Some(name) => {
+            let path = Path::new(args.value_of("profiles").unwrap());
+            Profiles::load(path)
+                .and_then(|profiles| profiles.get(name).cloned())
+                .unwrap_or_else(|err| Error::with_description(&err, ErrorKind::InvalidValue).exit())
+        }
+        None => Profile::default(),
+    };
+    command_line.or(profile).or(defaults)
+}
+
+/// The player described by `player_args`, searching to `default_depth` unless
+/// told otherwise
+fn parse_player(args: &ArgMatches<'_>, default_depth: Option<usize>) -> PlayerConfig {
+    let defaults = Profile {
+        limit: default_depth.map(Limit::Depth),
+        ..Profile::default()
+    };
+    player(&parse_profile(args, defaults))
+}
+
+fn player(profile: &Profile) -> PlayerConfig {
+    if profile.limit.is_none() {
+        Error::with_description(
+            "either --depth or --time is required",
+            ErrorKind::MissingRequiredArgument,
+        )
+        .exit()
+    }
+    profile
+        .player()
+        .unwrap_or_else(|err| Error::with_description(&err, ErrorKind::InvalidValue).exit())
+}
+
+/// The start of a game under the rules given by `rules_arg`
+fn parse_start(args: &ArgMatches<'_>) -> Game {
+    Game {
+        first_move: !args.is_present("no-pie-rule"),
+        ..Game::default()
+    }
+}
+
+/// Exit for a number of opening plies that no random game lasts
+fn plies_error(name: &str, err: String) -> ! {
+    Error::with_description(
+        &format!("invalid {}: {}", name, err),
+        ErrorKind::InvalidValue,
+    )
+    .exit()
+}
+
+/// A search depth, which has to be at least one ply to find a move
+fn parse_depth(args: &ArgMatches<'_>) -> usize { parse_arg::<NonZeroUsize>(args, "depth").get() }
+
+fn parse_arg<T>(args: &ArgMatches<'_>, name: &str) -> T
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = args.value_of(name).unwrap();
+    value.parse().unwrap_or_else(|err| {
+        Error::with_description(
+            &format!("invalid {} `{}`: {}", name, value, err),
+            ErrorKind::InvalidValue,
+        )
+        .exit()
+    })
+}
 
 fn main() {
     // run with `RUST_LOG=debug cargo run --bin mankalah ...`
@@ -15,36 +252,1244 @@ fn main() {
     let args = App::new("Mankalah")
         .version("1.0")
         .author("Karl Meakin & Ben Maxwell")
-        .arg(
-            Arg::with_name("search")
-                .long("search")
-                .possible_values(&["minimax", "alpha-beta"])
-                .default_value("alpha-beta"),
-        )
-        .arg(
-            Arg::with_name("weight")
-                .long("weights")
-                .number_of_values(5)
-                .required(true),
-        )
-        .arg(
-            Arg::with_name("depth")
-                .long("depth")
-                .takes_value(true)
-                .required(true),
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("agent")
+                .about("Play a match over the referee's protocol on stdin and stdout, or over TCP")
+                .args(&player_args("Search every move this many plies deep"))
+                .arg(
+                    Arg::with_name("explain")
+                        .long("explain")
+                        .help("Log the evaluation of the principal leaf of every move we make"),
+                )
+                .arg(
+                    Arg::with_name("ponder")
+                        .long("ponder")
+                        .help("Search on the opponent's time, for the reply we expect"),
+                )
+                .arg(
+                    Arg::with_name("connect")
+                        .long("connect")
+                        .value_name("HOST:PORT")
+                        .help(
+                            "Connect to the engine at this address instead of using stdin and \
+                             stdout",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("listen")
+                        .long("listen")
+                        .value_name("PORT")
+                        .help("Play every engine that connects to this port, one at a time")
+                        .takes_value(true)
+                        .conflicts_with("connect"),
+                )
+                .arg(
+                    Arg::with_name("reconnects")
+                        .long("reconnects")
+                        .help("Times to connect again if the engine can't be reached or hangs up")
+                        .takes_value(true)
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::with_name("matches")
+                        .long("matches")
+                        .help("Stop listening after this many matches have been played to the end")
+                        .takes_value(true)
+                        .requires("listen"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("play")
+                .about("Play a game against the engine in the terminal")
+                .args(&player_args(
+                    "Search every move this many plies deep [default: 8]",
+                ))
+                .arg(
+                    Arg::with_name("side")
+                        .long("side")
+                        .help("The side you start on")
+                        .possible_values(&["North", "South"])
+                        .default_value("South"),
+                )
+                .arg(rules_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("perft")
+                .about("Count the positions every number of plies ahead, to check move generation")
+                .arg(Arg::with_name("depth").required(true))
+                .arg(
+                    Arg::with_name("state")
+                        .long("state")
+                        .help("Board to start from, as for `analyze`, instead of the start")
+                        .takes_value(true)
+                        .conflicts_with("no-pie-rule"),
+                )
+                .arg(
+                    Arg::with_name("to-move")
+                        .long("to-move")
+                        .possible_values(&["North", "South"])
+                        .default_value("South"),
+                )
+                .arg(
+                    Arg::with_name("first-move")
+                        .long("first-move")
+                        .help("The pie rule has not been used yet, with --state"),
+                )
+                .arg(
+                    Arg::with_name("divide")
+                        .long("divide")
+                        .help("Also count the positions after each move at the full depth"),
+                )
+                .arg(rules_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("analyze")
+                .about("Print the evaluation of a position, term by term")
+                .arg(
+                    Arg::with_name("state")
+                        .help(
+                            "Board in the protocol's format, North first, e.g. \
+                             7,7,7,7,7,7,7,0,7,7,7,7,7,7,7,0",
+                        )
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("to-move")
+                        .long("to-move")
+                        .possible_values(&["North", "South"])
+                        .default_value("South"),
+                )
+                .arg(
+                    Arg::with_name("first-move")
+                        .long("first-move")
+                        .help("The pie rule has not been used yet"),
+                )
+                .args(&player_args(
+                    "Also search the position this deep and explain the principal leaf",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("tournament")
+                .about("Play agent configs against each other and report results with Elo")
+                .arg(
+                    Arg::with_name("agent")
+                        .long("agent")
+                        .help(
+                            "An agent as `name;key=value;...` with keys search, depth, time (ms \
+                             per move), weights and network, e.g. \
+                             \"deep;search=mtd-f;depth=8;weights=score=1 capture=0.6\"",
+                        )
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .min_values(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .help("In a gauntlet the first agent plays all the others")
+                        .possible_values(&Format::NAMES)
+                        .default_value("round-robin"),
+                )
+                .arg(
+                    Arg::with_name("match-openings")
+                        .long("openings")
+                        .help("Random openings every pair plays, once with each colour")
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::with_name("openings-file")
+                        .long("openings-file")
+                        .help(
+                            "Play the openings in this file, written by `openings`, instead of \
+                             random ones",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("opening-plies")
+                        .long("opening-plies")
+                        .default_value("4"),
+                )
+                .arg(Arg::with_name("seed").long("seed").default_value("0"))
+                .arg(history_arg())
+                .arg(
+                    Arg::with_name("csv")
+                        .long("csv")
+                        .help("Also write every game to this CSV file")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Also write every game to this JSON file")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("compare")
+                .about("Compare the results of two runs, per config and opponent")
+                .arg(
+                    Arg::with_name("a")
+                        .help("Git revision in the history directory, or a CSV file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("b")
+                        .help("Git revision in the history directory, or a CSV file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("all-runs")
+                        .long("all-runs")
+                        .help("Use every run of a revision instead of the latest"),
+                )
+                .arg(history_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("openings")
+                .about("Write a suite of random openings that a shallow search finds balanced")
+                .arg(weights_arg())
+                .arg(search_arg())
+                .arg(Arg::with_name("depth").long("depth").default_value("6"))
+                .arg(Arg::with_name("count").long("count").default_value("50"))
+                .arg(
+                    Arg::with_name("plies")
+                        .long("plies")
+                        .help("Random moves in each opening")
+                        .default_value("4"),
+                )
+                .arg(
+                    Arg::with_name("threshold")
+                        .long("threshold")
+                        .help("Largest evaluation, in seeds, either side of zero that is balanced")
+                        .default_value("2"),
+                )
+                .arg(
+                    Arg::with_name("max-attempts")
+                        .long("max-attempts")
+                        .default_value("100000"),
+                )
+                .arg(Arg::with_name("seed").long("seed").default_value("0"))
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sprt")
+                .about(
+                    "Play a candidate against a baseline until a sequential probability ratio \
+                     test decides. Exits with 0 if the candidate is better, 1 if it is not and 2 \
+                     if the test ran out of games",
+                )
+                .arg(
+                    Arg::with_name("baseline")
+                        .long("baseline")
+                        .help("Agent as for `tournament --agent`")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("candidate")
+                        .long("candidate")
+                        .help("Agent as for `tournament --agent`")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("elo0")
+                        .long("elo0")
+                        .help("Elo difference of the null hypothesis")
+                        .default_value("0")
+                        .allow_hyphen_values(true),
+                )
+                .arg(
+                    Arg::with_name("elo1")
+                        .long("elo1")
+                        .help("Elo difference of the alternative hypothesis")
+                        .default_value("10")
+                        .allow_hyphen_values(true),
+                )
+                .arg(
+                    Arg::with_name("alpha")
+                        .long("alpha")
+                        .help("Chance of accepting a change that is no better")
+                        .default_value("0.05"),
+                )
+                .arg(
+                    Arg::with_name("beta")
+                        .long("beta")
+                        .help("Chance of rejecting a change that is better")
+                        .default_value("0.05"),
+                )
+                .arg(
+                    Arg::with_name("max-games")
+                        .long("max-games")
+                        .default_value("20000"),
+                )
+                .arg(
+                    Arg::with_name("openings-file")
+                        .long("openings-file")
+                        .help(
+                            "Play the openings in this file, written by `openings`, instead of \
+                             random ones",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("opening-plies")
+                        .long("opening-plies")
+                        .default_value("4"),
+                )
+                .arg(Arg::with_name("seed").long("seed").default_value("0")),
+        )
+        .subcommand(
+            SubCommand::with_name("train")
+                .about("Train a network evaluator on positions written by `selfplay`")
+                .arg(
+                    Arg::with_name("dataset")
+                        .long("dataset")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("hidden")
+                        .long("hidden")
+                        .help("Hidden units of a new network")
+                        .default_value("16"),
+                )
+                .arg(
+                    Arg::with_name("k")
+                        .long("k")
+                        .help(
+                            "Scale of a new network's output, per seed, in the log-odds of winning",
+                        )
+                        .default_value("0.1"),
+                )
+                .arg(
+                    Arg::with_name("init")
+                        .long("init")
+                        .help("Carry on training this network instead of a new one")
+                        .takes_value(true),
+                )
+                .arg(Arg::with_name("epochs").long("epochs").default_value("20"))
+                .arg(
+                    Arg::with_name("learning-rate")
+                        .long("learning-rate")
+                        .default_value("0.001"),
+                )
+                .arg(Arg::with_name("seed").long("seed").default_value("0"))
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .default_value("network.mlp"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("selfplay")
+                .about("Play games against itself and write every position with its outcome")
+                .args(&player_args(
+                    "Search every move this many plies deep [default: 4]",
+                ))
+                .arg(Arg::with_name("games").long("games").default_value("100"))
+                .arg(
+                    Arg::with_name("opening-plies")
+                        .long("opening-plies")
+                        .help("Random moves at the start of every game")
+                        .default_value("4"),
+                )
+                .arg(Arg::with_name("seed").long("seed").default_value("0"))
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .help("Dataset file to write, one `board player outcome` per line")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("tune")
+                .about("Tune heuristic weights")
+                .arg(weights_arg().help("Weights to start from, as name=value pairs"))
+                .arg(
+                    Arg::with_name("method")
+                        .long("method")
+                        .help(
+                            "`ga` evolves weights by playing games, `texel` fits them to the \
+                             outcomes in a dataset, `td` learns them by TD(lambda) self-play",
+                        )
+                        .possible_values(&["ga", "texel", "td"])
+                        .default_value("ga"),
+                )
+                .arg(search_arg())
+                .arg(
+                    Arg::with_name("depth")
+                        .long("depth")
+                        .help("Search depth of every player")
+                        .default_value("4"),
+                )
+                .arg(
+                    Arg::with_name("terms")
+                        .long("terms")
+                        .help("Names of the weights to tune. The others are left as given")
+                        .takes_value(true)
+                        .multiple(true)
+                        .use_delimiter(true),
+                )
+                .arg(
+                    Arg::with_name("population")
+                        .long("population")
+                        .default_value("16"),
+                )
+                .arg(
+                    Arg::with_name("generations")
+                        .long("generations")
+                        .default_value("20"),
+                )
+                .arg(Arg::with_name("elitism").long("elitism").default_value("2"))
+                .arg(
+                    Arg::with_name("crossover-rate")
+                        .long("crossover-rate")
+                        .default_value("0.7"),
+                )
+                .arg(
+                    Arg::with_name("mutation-rate")
+                        .long("mutation-rate")
+                        .help("Chance that each weight of a child is mutated")
+                        .default_value("0.2"),
+                )
+                .arg(
+                    Arg::with_name("mutation-size")
+                        .long("mutation-size")
+                        .help("Standard deviation of a mutation")
+                        .default_value("0.2"),
+                )
+                .arg(
+                    Arg::with_name("tournament-size")
+                        .long("tournament-size")
+                        .default_value("3"),
+                )
+                .arg(Arg::with_name("seed").long("seed").default_value("0"))
+                .arg(
+                    Arg::with_name("opponents")
+                        .long("opponents")
+                        .help("Members of the population each individual plays in self-play")
+                        .default_value("4"),
+                )
+                .arg(
+                    Arg::with_name("reference")
+                        .long("reference")
+                        .help(
+                            "Measure fitness against these weights instead of by self-play. Quote \
+                             each set, e.g. --reference \"score=1 capture=0.6\"",
+                        )
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("checkpoint")
+                        .long("checkpoint")
+                        .help("File the population is saved to after every generation")
+                        .default_value("tune.checkpoint"),
+                )
+                .arg(
+                    Arg::with_name("resume")
+                        .long("resume")
+                        .help("Carry on from the checkpoint file"),
+                )
+                .arg(
+                    Arg::with_name("dataset")
+                        .long("dataset")
+                        .help("Positions and outcomes written by `selfplay`")
+                        .takes_value(true)
+                        .required_if("method", "texel"),
+                )
+                .arg(
+                    Arg::with_name("iterations")
+                        .long("iterations")
+                        .default_value("1000"),
+                )
+                .arg(
+                    Arg::with_name("learning-rate")
+                        .long("learning-rate")
+                        .default_value("0.01"),
+                )
+                .arg(
+                    Arg::with_name("match-openings")
+                        .long("match-openings")
+                        .help(
+                            "Random openings the tuned weights play the initial ones from, once \
+                             with each colour",
+                        )
+                        .default_value("20"),
+                )
+                .arg(
+                    Arg::with_name("opening-plies")
+                        .long("opening-plies")
+                        .default_value("4"),
+                )
+                .arg(
+                    Arg::with_name("games")
+                        .long("games")
+                        .help("Self-play games to learn from")
+                        .default_value("1000"),
+                )
+                .arg(
+                    Arg::with_name("lambda")
+                        .long("lambda")
+                        .help("Decay of the eligibility traces")
+                        .default_value("0.7"),
+                )
+                .arg(
+                    Arg::with_name("epsilon")
+                        .long("epsilon")
+                        .help("Chance of exploring with a random move")
+                        .default_value("0.1"),
+                )
+                .arg(
+                    Arg::with_name("k")
+                        .long("k")
+                        .help(
+                            "Scale of the evaluation, per seed, in the predicted chance of winning",
+                        )
+                        .default_value("0.1"),
+                )
+                .arg(
+                    Arg::with_name("baseline")
+                        .long("baseline")
+                        .help(
+                            "Weights the learning curve is measured against. Defaults to --weights",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("eval-every")
+                        .long("eval-every")
+                        .help("Games between matches against the baseline")
+                        .default_value("100"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .help("File the learned weights are saved to, for use as --weights @FILE")
+                        .default_value("learned.weights"),
+                ),
         )
         .get_matches();
-    let depth: usize = args.value_of("depth").unwrap().parse().unwrap();
-    let weights: Vec<f32> = args
-        .values_of("weight")
+
+    match args.subcommand() {
+        ("agent", Some(args)) => run_agent(args),
+        ("play", Some(args)) => play(args),
+        ("perft", Some(args)) => perft(args),
+        ("analyze", Some(args)) => analyze(args),
+        ("tournament", Some(args)) => run_tournament(args),
+        ("compare", Some(args)) => compare(args),
+        ("openings", Some(args)) => write_opening_suite(args),
+        ("sprt", Some(args)) => std::process::exit(run_sprt(args)),
+        ("train", Some(args)) => train(args),
+        ("selfplay", Some(args)) => selfplay(args),
+        ("tune", Some(args)) => match args.value_of("method") {
+            Some("ga") => tune_ga(args),
+            Some("texel") => tune_texel(args),
+            Some("td") => tune_td(args),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+fn run_agent(args: &ArgMatches<'_>) {
+    let profile = parse_profile(args, Profile::default());
+    let player = player(&profile);
+    let mut agent = Agent::with_tt_entries(profile.tt_entries());
+    agent.set_explain(args.is_present("explain"));
+    agent.set_ponder(profile.ponder.unwrap_or(false));
+    if let Some(book) = &profile.book {
+        warn!(
+            "the agent has no opening book yet, so {} is not used",
+            book.display()
+        );
+    }
+    let result = if let Some(addr) = args.value_of("connect") {
+        let reconnect = net::Reconnect {
+            attempts: parse_arg(args, "reconnects"),
+            ..net::Reconnect::default()
+        };
+        net::connect(&mut agent, &player, addr, reconnect)
+    } else if args.is_present("listen") {
+        let port: u16 = parse_arg(args, "listen");
+        let matches = args.value_of("matches").map(|_| parse_arg(args, "matches"));
+        TcpListener::bind(("0.0.0.0", port))
+            .map_err(AgentError::from)
+            .and_then(|listener| net::listen(&mut agent, &player, &listener, matches))
+    } else {
+        agent.run(&player)
+    };
+    result.unwrap_or_else(|err| Error::with_description(&err.to_string(), ErrorKind::Io).exit());
+}
+
+fn parse_state(args: &ArgMatches<'_>) -> BoardState {
+    let state = args.value_of("state").unwrap();
+    ProtocolGrammar::State(state).unwrap_or_else(|err| {
+        Error::with_description(
+            &format!("invalid board state `{}`: {}", state, err),
+            ErrorKind::InvalidValue,
+        )
+        .exit()
+    })
+}
+
+fn analyze(args: &ArgMatches<'_>) {
+    let board = parse_state(args);
+    let pos = ProtocolGrammar::Position(args.value_of("to-move").unwrap()).unwrap();
+    let first_move = args.is_present("first-move");
+    let profile = parse_profile(args, Profile::default());
+    let weights = profile.weights.unwrap_or_else(|| {
+        Error::with_description("the profile has no weights", ErrorKind::InvalidValue).exit()
+    });
+    let network = profile
+        .network()
+        .unwrap_or_else(|err| Error::with_description(&err, ErrorKind::InvalidValue).exit());
+
+    println!("board: {}, {} to move", board, pos);
+    println!("{}", explain(weights, &board, pos));
+    if let Some(network) = &network {
+        println!("network: {}", network.evaluate(&board, pos));
+    }
+
+    let limit = match profile.limit {
+        Some(limit) => limit,
+        None => return,
+    };
+    let algorithm = profile.algorithm();
+    let mut search = Search::with_tt_entries(profile.tt_entries());
+    search.network = network;
+    let depths = match limit {
+        Limit::Depth(depth) => depth..=depth,
+        Limit::Time(_) => 1..=MAX_PLY,
+    };
+    let start = Instant::now();
+    println!();
+    for depth in depths {
+        let (best_move, score) =
+            algorithm.eval(&mut search, board, pos, 0, first_move, depth, weights);
+        let best_move = match best_move {
+            Some(best_move) => AgentMessage::from(best_move)
+                .to_string()
+                .trim_end()
+                .to_owned(),
+            None => "none, the game is over".to_owned(),
+        };
+        println!(
+            "depth {}: best move {}, score {} ({})",
+            depth, best_move, score, search.stats
+        );
+        match limit {
+            Limit::Time(time) if start.elapsed() < time / 2 => {}
+            _ => break,
+        }
+    }
+    if let Some((leaf, leaf_pos)) = search.principal_leaf() {
+        println!("principal leaf: {}, {} to move", leaf, leaf_pos);
+        println!("{}", explain(weights, &leaf, leaf_pos));
+    }
+}
+
+fn perft(args: &ArgMatches<'_>) {
+    let game = if args.is_present("state") {
+        Game {
+            board: parse_state(args),
+            to_move: ProtocolGrammar::Position(args.value_of("to-move").unwrap()).unwrap(),
+            first_move: args.is_present("first-move"),
+        }
+    } else {
+        parse_start(args)
+    };
+    let depth: usize = parse_arg(args, "depth");
+    for depth in 1..=depth {
+        let start = Instant::now();
+        let positions = game::perft(game, depth);
+        let elapsed = start.elapsed();
+        println!(
+            "depth {}: {} positions in {}ms ({:.0} per second)",
+            depth,
+            positions,
+            elapsed.as_millis(),
+            positions as f64 / elapsed.as_secs_f64().max(1e-9)
+        );
+    }
+    if args.is_present("divide") && depth > 0 {
+        println!();
+        for the_move in game.legal_moves() {
+            let mut child = game;
+            child.play(the_move);
+            println!("{}: {}", move_name(the_move), game::perft(child, depth - 1));
+        }
+    }
+}
+
+/// `the_move` as it is typed in `play`
+fn move_name(the_move: PlayerMove) -> String {
+    match the_move {
+        PlayerMove::Move { n } => (n + 1).to_string(),
+        PlayerMove::Swap => "swap".to_owned(),
+    }
+}
+
+/// Print `game` with the pits numbered as moves are typed, our side at the
+/// bottom, and whose turn it is
+fn print_board(game: &Game, human: Position) {
+    let row = |pos: Position| -> Vec<String> {
+        game.board[pos]
+            .pits
+            .iter()
+            .map(|seeds| format!("{:>2}", seeds))
+            .collect()
+    };
+    let mut theirs = row(!human);
+    theirs.reverse();
+    println!();
+    println!(
+        "      {}",
+        (1..=7)
+            .rev()
+            .map(|n| format!("{:>2}", n))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    println!("  {:>2}  {}", game.board[!human].score, theirs.join(" "));
+    println!(
+        "      {}  {:>2}",
+        row(human).join(" "),
+        game.board[human].score
+    );
+    println!(
+        "      {}",
+        (1..=7)
+            .map(|n| format!("{:>2}", n))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+}
+
+fn play(args: &ArgMatches<'_>) {
+    let profile = parse_profile(
+        args,
+        Profile {
+            limit: Some(Limit::Depth(8)),
+            ..Profile::default()
+        },
+    );
+    let engine = player(&profile);
+    let human = ProtocolGrammar::Position(args.value_of("side").unwrap()).unwrap();
+    let mut game = parse_start(args);
+    let mut search = Search::with_tt_entries(profile.tt_entries());
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    println!(
+        "You are {}, the engine searches to {}.",
+        human, engine.limit
+    );
+    println!("Type a pit from 1 to 7, or `swap` when the pie rule allows it.");
+    loop {
+        print_board(&game, human);
+        if let Some(margin) = game.result() {
+            let margin = match human {
+                Position::South => margin,
+                Position::North => -margin,
+            };
+            match margin {
+                0 => println!("Draw."),
+                margin if margin > 0 => println!("You win by {}.", margin),
+                margin => println!("You lose by {}.", -margin),
+            }
+            return;
+        }
+        let the_move = if game.to_move == human {
+            print!("your move: ");
+            io::stdout().flush().unwrap_or(());
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                _ => return,
+            };
+            let the_move = match line.trim() {
+                "swap" => Some(PlayerMove::Swap),
+                pit => match pit.parse::<u8>() {
+                    Ok(n @ 1..=7) => Some(PlayerMove::Move { n: n - 1 }),
+                    _ => None,
+                },
+            };
+            match the_move {
+                Some(the_move) if game.legal_moves().any(|legal| legal == the_move) => the_move,
+                _ => {
+                    println!("`{}` is not a legal move", line.trim());
+                    continue;
+                }
+            }
+        } else {
+            let the_move = engine.choose_move(&mut search, &game);
+            println!("engine plays {} ({})", move_name(the_move), search.stats);
+            the_move
+        };
+        game.play(the_move);
+    }
+}
+
+/// Which weights `--terms` allows to be tuned: all of them if it is not given
+fn parse_terms(args: &ArgMatches<'_>) -> [bool; NUM_HEURISTICS] {
+    let names = match args.values_of("terms") {
+        None => return [true; NUM_HEURISTICS],
+        Some(names) => names,
+    };
+    let mut tuned = [false; NUM_HEURISTICS];
+    for name in names {
+        match heuristic_index(name) {
+            Some(idx) => tuned[idx] = true,
+            None => Error::with_description(
+                &format!("unknown heuristic `{}`", name),
+                ErrorKind::InvalidValue,
+            )
+            .exit(),
+        }
+    }
+    tuned
+}
+
+fn io_error(what: &str, path: &Path, err: io::Error) -> ! {
+    Error::with_description(
+        &format!("{} `{}`: {}", what, path.display(), err),
+        ErrorKind::Io,
+    )
+    .exit()
+}
+
+fn selfplay(args: &ArgMatches<'_>) {
+    let player = parse_player(args, Some(4));
+    let samples = texel::collect_samples(
+        &player,
+        parse_arg(args, "games"),
+        parse_arg(args, "opening-plies"),
+        parse_arg(args, "seed"),
+    )
+    .unwrap_or_else(|err| plies_error("opening-plies", err));
+    let path = Path::new(args.value_of("output").unwrap());
+    fs::File::create(path)
+        .and_then(|file| texel::write_samples(io::BufWriter::new(file), &samples))
+        .unwrap_or_else(|err| io_error("cannot write dataset", path, err));
+    println!("wrote {} positions to {}", samples.len(), path.display());
+}
+
+fn parse_entrant(agent: &str) -> Entrant {
+    agent.parse().unwrap_or_else(|err| {
+        Error::with_description(&format!("invalid agent: {}", err), ErrorKind::InvalidValue).exit()
+    })
+}
+
+/// Exit code: 0 if the candidate passed
+fn run_sprt(args: &ArgMatches<'_>) -> i32 {
+    let baseline = parse_entrant(args.value_of("baseline").unwrap());
+    let candidate = parse_entrant(args.value_of("candidate").unwrap());
+    let sprt = Sprt {
+        elo0: parse_arg(args, "elo0"),
+        elo1: parse_arg(args, "elo1"),
+        alpha: parse_arg(args, "alpha"),
+        beta: parse_arg(args, "beta"),
+    };
+    let openings: Box<dyn Iterator<Item = Game>> = match args.value_of("openings-file") {
+        Some(path) => Box::new(read_openings(Path::new(path)).into_iter().cycle()),
+        None => {
+            let plies = parse_arg(args, "opening-plies");
+            let mut rng = StdRng::seed_from_u64(parse_arg(args, "seed"));
+            Box::new(std::iter::repeat_with(move || {
+                random_opening(&mut rng, plies)
+                    .unwrap_or_else(|err| plies_error("opening-plies", err))
+            }))
+        }
+    };
+    println!(
+        "{} vs {}: H0 elo <= {}, H1 elo >= {}, LLR bounds ({:.3}, {:.3})",
+        candidate.name,
+        baseline.name,
+        sprt.elo0,
+        sprt.elo1,
+        sprt.lower_bound(),
+        sprt.upper_bound()
+    );
+    let (decision, result) = tournament::run_sprt(
+        &candidate.player,
+        &baseline.player,
+        &sprt,
+        parse_arg(args, "max-games"),
+        openings,
+        |result, llr| {
+            println!(
+                "games {}: {}, elo {}, LLR {:.3}",
+                result.games(),
+                result,
+                EloEstimate::new(result),
+                llr
+            )
+        },
+    );
+    println!();
+    match decision {
+        SprtDecision::AcceptH1 => {
+            println!(
+                "H1 accepted: {} is better after {} games",
+                candidate.name,
+                result.games()
+            );
+            0
+        }
+        SprtDecision::AcceptH0 => {
+            println!(
+                "H0 accepted: {} is not better after {} games",
+                candidate.name,
+                result.games()
+            );
+            1
+        }
+        SprtDecision::Continue => {
+            println!("no decision after {} games", result.games());
+            2
+        }
+    }
+}
+
+fn write_opening_suite(args: &ArgMatches<'_>) {
+    let config = SuiteConfig {
+        count: parse_arg(args, "count"),
+        plies: parse_arg(args, "plies"),
+        algorithm: parse_arg(args, "search"),
+        depth: parse_depth(args),
+        weights: parse_weights(args),
+        threshold: parse_arg(args, "threshold"),
+        max_attempts: parse_arg(args, "max-attempts"),
+        seed: parse_arg(args, "seed"),
+    };
+    let suite =
+        openings::balanced_openings(&config).unwrap_or_else(|err| plies_error("plies", err));
+    if suite.len() < config.count {
+        eprintln!(
+            "only found {} balanced openings in {} attempts",
+            suite.len(),
+            config.max_attempts
+        );
+    }
+    let path = Path::new(args.value_of("output").unwrap());
+    fs::File::create(path)
+        .and_then(|file| {
+            let mut out = io::BufWriter::new(file);
+            writeln!(
+                out,
+                "# {} plies, |{} depth {}| <= {} seeds, weights {}",
+                config.plies, config.algorithm, config.depth, config.threshold, config.weights
+            )?;
+            openings::write_openings(out, &suite)
+        })
+        .unwrap_or_else(|err| io_error("cannot write openings", path, err));
+    println!("wrote {} openings to {}", suite.len(), path.display());
+}
+
+fn run_tournament(args: &ArgMatches<'_>) {
+    let entrants: Vec<Entrant> = args
+        .values_of("agent")
         .unwrap()
-        .map(|w| f32::from_str(w).unwrap())
+        .map(parse_entrant)
         .collect();
-    let weights: Weights = weights.try_into().unwrap();
-    let mut agent = Agent::new();
-    match args.value_of("search") {
-        Some("minimax") => agent.run::<MiniMax>(depth, weights),
-        Some("alpha-beta") => agent.run::<AlphaBeta>(depth, weights),
-        _ => unreachable!(),
+    let format: Format = parse_arg(args, "format");
+    let openings = random_openings(args);
+    let tournament = tournament::run(&entrants, format, &openings, |pairing| {
+        println!(
+            "{} vs {}: {}",
+            entrants[pairing.first].name, entrants[pairing.second].name, pairing.result
+        )
+    });
+    println!();
+    print!("{}", tournament);
+
+    let games = tournament.results(&results::git_revision(), results::now());
+    let history = Path::new(args.value_of("history").unwrap());
+    results::append_history(history, &games)
+        .unwrap_or_else(|err| io_error("cannot append to history", history, err));
+    if let Some(path) = args.value_of("csv") {
+        let path = Path::new(path);
+        fs::File::create(path)
+            .and_then(|file| results::write_csv(io::BufWriter::new(file), &games))
+            .unwrap_or_else(|err| io_error("cannot write", path, err));
+    }
+    if let Some(path) = args.value_of("json") {
+        let path = Path::new(path);
+        fs::File::create(path)
+            .and_then(|file| results::write_json(io::BufWriter::new(file), &games))
+            .unwrap_or_else(|err| io_error("cannot write", path, err));
+    }
+}
+
+/// Games of the run called `name`: a CSV file, or a revision in the history
+fn load_run(args: &ArgMatches<'_>, name: &str) -> Vec<GameResult> {
+    let path = if Path::new(name).is_file() {
+        PathBuf::from(name)
+    } else {
+        results::history_file(Path::new(args.value_of("history").unwrap()), name)
+    };
+    let games = results::read_csv(&path).unwrap_or_else(|err| io_error("cannot read", &path, err));
+    if args.is_present("all-runs") {
+        games
+    } else {
+        results::latest_run(games)
+    }
+}
+
+fn compare(args: &ArgMatches<'_>) {
+    let a = load_run(args, args.value_of("a").unwrap());
+    let b = load_run(args, args.value_of("b").unwrap());
+    println!("A: {} games, B: {} games", a.len(), b.len());
+    println!();
+    print!("{}", results::compare(&a, &b));
+}
+
+fn train(args: &ArgMatches<'_>) {
+    let path = Path::new(args.value_of("dataset").unwrap());
+    let samples =
+        texel::read_samples(path).unwrap_or_else(|err| io_error("cannot read dataset", path, err));
+    let seed = parse_arg(args, "seed");
+    let mut network = match args.value_of("init") {
+        Some(init) => Network::load(Path::new(init))
+            .unwrap_or_else(|err| io_error("cannot read network", Path::new(init), err)),
+        None => {
+            let hidden: usize = parse_arg(args, "hidden");
+            if hidden > nn::MAX_HIDDEN {
+                Error::with_description(
+                    &format!("at most {} hidden units", nn::MAX_HIDDEN),
+                    ErrorKind::InvalidValue,
+                )
+                .exit()
+            }
+            Network::new(hidden, parse_arg(args, "k"), seed)
+        }
+    };
+    println!(
+        "{} positions, {} hidden units",
+        samples.len(),
+        network.hidden()
+    );
+
+    let output = Path::new(args.value_of("output").unwrap());
+    nn::train(
+        &mut network,
+        &samples,
+        parse_arg(args, "epochs"),
+        parse_arg(args, "learning-rate"),
+        seed,
+        |epoch, loss| println!("epoch {}: loss {:.6}", epoch, loss),
+    );
+    network
+        .save(output)
+        .unwrap_or_else(|err| io_error("cannot save network", output, err));
+    println!();
+    println!("--eval mlp --network {}", output.display());
+}
+
+fn tune_texel(args: &ArgMatches<'_>) {
+    let initial = parse_weights(args);
+    let path = Path::new(args.value_of("dataset").unwrap());
+    let samples =
+        texel::read_samples(path).unwrap_or_else(|err| io_error("cannot read dataset", path, err));
+    let config = TexelConfig {
+        iterations: parse_arg(args, "iterations"),
+        learning_rate: parse_arg(args, "learning-rate"),
+        tuned: parse_terms(args),
+    };
+
+    let k = texel::fit_k(&initial, &samples);
+    let initial_loss = texel::loss(&initial, k, &samples);
+    println!(
+        "{} positions, k = {}, initial loss {:.6}",
+        samples.len(),
+        k,
+        initial_loss
+    );
+    let report_every = (config.iterations / 20).max(1);
+    let tuned = texel::tune(&config, initial, k, &samples, |iteration, weights, loss| {
+        if iteration % report_every == 0 {
+            println!("iteration {}: loss {:.6}, {}", iteration, loss, weights);
+        }
+    });
+
+    let algorithm: Algorithm = parse_arg(args, "search");
+    let depth = parse_depth(args);
+    let openings = random_openings(args);
+    let result = play_match(
+        &PlayerConfig::new(algorithm, depth, tuned),
+        &PlayerConfig::new(algorithm, depth, initial),
+        &openings,
+    );
+    println!();
+    println!(
+        "loss: initial {:.6}, tuned {:.6}",
+        initial_loss,
+        texel::loss(&tuned, k, &samples)
+    );
+    println!("tuned vs initial at depth {}: {}", depth, result);
+    println!("--weights {}", tuned);
+}
+
+fn read_openings(path: &Path) -> Vec<Game> {
+    let openings = openings::read_openings(path)
+        .unwrap_or_else(|err| io_error("cannot read openings", path, err));
+    if openings.is_empty() {
+        Error::with_description(
+            &format!("no openings in `{}`", path.display()),
+            ErrorKind::InvalidValue,
+        )
+        .exit()
+    }
+    openings
+}
+
+/// The openings in `--openings-file` if it is given, otherwise
+/// `--match-openings` random ones
+fn random_openings(args: &ArgMatches<'_>) -> Vec<Game> {
+    if let Some(path) = args.value_of("openings-file") {
+        return read_openings(Path::new(path));
     }
+    let count: usize = parse_arg(args, "match-openings");
+    let plies = parse_arg(args, "opening-plies");
+    let mut rng = StdRng::seed_from_u64(parse_arg(args, "seed"));
+    (0..count)
+        .map(|_| random_opening(&mut rng, plies))
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|err| plies_error("opening-plies", err))
+}
+
+fn tune_td(args: &ArgMatches<'_>) {
+    let initial = parse_weights(args);
+    let baseline = match args.value_of("baseline") {
+        Some(weights) => weights.parse().unwrap_or_else(|err| {
+            Error::with_description(
+                &format!("invalid baseline `{}`: {}", weights, err),
+                ErrorKind::InvalidValue,
+            )
+            .exit()
+        }),
+        None => initial,
+    };
+    let config = TdConfig {
+        alpha: parse_arg(args, "learning-rate"),
+        lambda: parse_arg(args, "lambda"),
+        epsilon: parse_arg(args, "epsilon"),
+        k: parse_arg(args, "k"),
+        opening_plies: parse_arg(args, "opening-plies"),
+        tuned: parse_terms(args),
+        seed: parse_arg(args, "seed"),
+    };
+    let games: usize = parse_arg(args, "games");
+    let eval_every: usize = parse_arg(args, "eval-every");
+    let algorithm: Algorithm = parse_arg(args, "search");
+    let depth = parse_depth(args);
+    let openings = random_openings(args);
+    let baseline = PlayerConfig::new(algorithm, depth, baseline);
+    let path = Path::new(args.value_of("output").unwrap());
+
+    let mut learner = Learner::new(config, initial);
+    while learner.games < games {
+        learner
+            .play_game()
+            .unwrap_or_else(|err| plies_error("opening-plies", err));
+        if learner.games.is_multiple_of(eval_every.max(1)) || learner.games == games {
+            let learned = PlayerConfig::new(algorithm, depth, learner.weights);
+            let result = play_match(&learned, &baseline, &openings);
+            info!(
+                "td: {} games, vs baseline {}, {}",
+                learner.games, result, learner.weights
+            );
+            println!(
+                "games {}: vs baseline {}, {}",
+                learner.games, result, learner.weights
+            );
+            learner
+                .weights
+                .save(path)
+                .unwrap_or_else(|err| io_error("cannot save weights", path, err));
+        }
+    }
+    println!();
+    println!("--weights @{}", path.display());
+}
+
+fn tune_ga(args: &ArgMatches<'_>) {
+    let initial = parse_weights(args);
+    let tuned = parse_terms(args);
+    let fitness = match args.values_of("reference") {
+        None => Fitness::SelfPlay {
+            opponents: parse_arg(args, "opponents"),
+        },
+        Some(references) => Fitness::Reference(
+            references
+                .map(|weights| {
+                    weights.parse().unwrap_or_else(|err| {
+                        Error::with_description(
+                            &format!("invalid reference `{}`: {}", weights, err),
+                            ErrorKind::InvalidValue,
+                        )
+                        .exit()
+                    })
+                })
+                .collect(),
+        ),
+    };
+    let algorithm: Algorithm = parse_arg(args, "search");
+    let config = GaConfig {
+        population: parse_arg(args, "population"),
+        generations: parse_arg(args, "generations"),
+        elitism: parse_arg(args, "elitism"),
+        crossover_rate: parse_arg(args, "crossover-rate"),
+        mutation_rate: parse_arg(args, "mutation-rate"),
+        mutation_size: parse_arg(args, "mutation-size"),
+        tournament_size: parse_arg(args, "tournament-size"),
+        tuned,
+        initial,
+        seed: parse_arg(args, "seed"),
+        player: PlayerConfig::new(algorithm, parse_depth(args), initial),
+        fitness,
+    };
+    config
+        .validate()
+        .unwrap_or_else(|err| Error::with_description(&err, ErrorKind::InvalidValue).exit());
+
+    let path = Path::new(args.value_of("checkpoint").unwrap());
+    let resume_from = if args.is_present("resume") {
+        let checkpoint =
+            Checkpoint::load(path).unwrap_or_else(|err| io_error("cannot resume from", path, err));
+        println!("resuming after generation {}", checkpoint.generation);
+        Some(checkpoint)
+    } else {
+        None
+    };
+
+    let last = ga::tune(&config, resume_from, |checkpoint| {
+        let best = checkpoint.best();
+        let mean = checkpoint
+            .population
+            .iter()
+            .map(|individual| individual.fitness)
+            .sum::<f32>()
+            / checkpoint.population.len() as f32;
+        println!(
+            "generation {}: best {:.2}, mean {:.2}, {}",
+            checkpoint.generation, best.fitness, mean, best.weights
+        );
+        checkpoint.save(path)
+    })
+    .unwrap_or_else(|err| io_error("cannot save checkpoint", path, err));
+    println!();
+    println!("--weights {}", last.best().weights);
 }
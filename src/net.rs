@@ -0,0 +1,215 @@
+//! Playing over TCP instead of stdin and stdout, for engines that run their
+//! agents remotely. The messages are the same, one per line.
+
+use crate::{
+    agent::{Agent, AgentError},
+    game::PlayerConfig,
+};
+use std::{
+    io::BufReader,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    thread,
+    time::Duration,
+};
+
+/// How hard to try when the engine can't be reached or hangs up
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Reconnect {
+    /// Failed connections to retry before giving up
+    pub attempts: usize,
+    pub delay: Duration,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self {
+            attempts: 10,
+            delay: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Whether another connection could get past `err`. Anything the engine
+/// said that we didn't follow would only happen again.
+fn can_retry(err: &AgentError) -> bool {
+    matches!(err, AgentError::Io(_) | AgentError::Disconnected)
+}
+
+/// Play a match over one connection
+pub fn play_stream(
+    agent: &mut Agent,
+    player: &PlayerConfig,
+    stream: TcpStream,
+) -> Result<(), AgentError> {
+    // every message is a line, so don't hold them back
+    stream.set_nodelay(true)?;
+    agent.reset();
+    let input = BufReader::new(stream.try_clone()?);
+    agent.play_match(player, input, stream)
+}
+
+/// Connect to the engine at `addr` and play a match. If the engine isn't
+/// listening yet, or hangs up before the end, connect again and play a new
+/// match, up to `reconnect.attempts` times.
+pub fn connect(
+    agent: &mut Agent,
+    player: &PlayerConfig,
+    addr: impl ToSocketAddrs,
+    reconnect: Reconnect,
+) -> Result<(), AgentError> {
+    let mut failures = 0;
+    loop {
+        let err = match TcpStream::connect(&addr) {
+            Ok(stream) => match play_stream(agent, player, stream) {
+                Ok(()) => return Ok(()),
+                Err(err) if can_retry(&err) => err,
+                Err(err) => return Err(err),
+            },
+            Err(err) => err.into(),
+        };
+        failures += 1;
+        if failures > reconnect.attempts {
+            return Err(err);
+        }
+        log::warn!(
+            "{}, reconnecting ({} of {})",
+            err,
+            failures,
+            reconnect.attempts
+        );
+        thread::sleep(reconnect.delay);
+    }
+}
+
+/// Play a match with every engine that connects to `listener`, one at a
+/// time, until `matches` have been played to the end. A connection that
+/// fails only ends its own match.
+pub fn listen(
+    agent: &mut Agent,
+    player: &PlayerConfig,
+    listener: &TcpListener,
+    matches: Option<usize>,
+) -> Result<(), AgentError> {
+    let mut played = 0;
+    while matches.is_none_or(|matches| played < matches) {
+        let (stream, peer) = listener.accept()?;
+        log::info!("playing {}", peer);
+        match play_stream(agent, player, stream) {
+            Ok(()) => played += 1,
+            Err(err) => log::warn!("match with {} abandoned: {}", peer, err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{eval::Algorithm, grammar::ProtocolGrammar};
+    use std::io::{BufRead, Write};
+
+    fn player() -> PlayerConfig {
+        PlayerConfig::new(Algorithm::AlphaBeta, 2, "score=1.0".parse().unwrap())
+    }
+
+    /// Stand in for the engine: start a match with us on South, wait for our
+    /// first move, then end the match early, as the engine does after a
+    /// timeout. Returns our move.
+    fn short_match(stream: TcpStream) -> String {
+        let mut output = stream.try_clone().unwrap();
+        let mut input = BufReader::new(stream);
+        output.write_all(b"START;South\n").unwrap();
+        let mut line = String::new();
+        input.read_line(&mut line).unwrap();
+        assert!(ProtocolGrammar::AgentMessage(&line).is_ok(), "{:?}", line);
+        output.write_all(b"END\n").unwrap();
+        line
+    }
+
+    /// Stand in for an engine that hangs up as soon as the match starts
+    fn hang_up(mut stream: TcpStream) { stream.write_all(b"START;South\n").unwrap(); }
+
+    #[test]
+    fn connect_plays_a_match() {
+        let engine = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = engine.local_addr().unwrap();
+        let engine = thread::spawn(move || short_match(engine.accept().unwrap().0));
+
+        let mut agent = Agent::with_tt_entries(1 << 10);
+        connect(&mut agent, &player(), addr, Reconnect::default()).unwrap();
+        assert!(agent.is_over());
+        assert!(engine.join().unwrap().starts_with("MOVE;"));
+    }
+
+    #[test]
+    fn connect_retries_until_the_engine_listens() {
+        // find a free port, then leave it closed for a while
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let engine = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            let engine = TcpListener::bind(addr).unwrap();
+            short_match(engine.accept().unwrap().0)
+        });
+
+        let mut agent = Agent::with_tt_entries(1 << 10);
+        let reconnect = Reconnect {
+            attempts: 50,
+            delay: Duration::from_millis(20),
+        };
+        connect(&mut agent, &player(), addr, reconnect).unwrap();
+        engine.join().unwrap();
+    }
+
+    #[test]
+    fn connect_again_after_a_hang_up() {
+        let engine = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = engine.local_addr().unwrap();
+        let engine = thread::spawn(move || {
+            hang_up(engine.accept().unwrap().0);
+            short_match(engine.accept().unwrap().0)
+        });
+
+        let mut agent = Agent::with_tt_entries(1 << 10);
+        let reconnect = Reconnect {
+            attempts: 1,
+            delay: Duration::from_millis(1),
+        };
+        connect(&mut agent, &player(), addr, reconnect).unwrap();
+        engine.join().unwrap();
+    }
+
+    #[test]
+    fn connect_gives_up() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut agent = Agent::with_tt_entries(1 << 10);
+        let reconnect = Reconnect {
+            attempts: 2,
+            delay: Duration::from_millis(1),
+        };
+        assert!(matches!(
+            connect(&mut agent, &player(), addr, reconnect),
+            Err(AgentError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn listen_outlasts_hang_ups() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let agent = thread::spawn(move || {
+            let mut agent = Agent::with_tt_entries(1 << 10);
+            listen(&mut agent, &player(), &listener, Some(2))
+        });
+
+        short_match(TcpStream::connect(addr).unwrap());
+        hang_up(TcpStream::connect(addr).unwrap());
+        short_match(TcpStream::connect(addr).unwrap());
+        agent.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+use crate::{
+    board::{BoardState, Position},
+    heuristics::{features, Score, DECISIVE, NUM_HEURISTICS, WEIGHT_SCALE},
+    texel::Sample,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{fmt, fs, io, path::Path, str::FromStr};
+
+/// Pits and store of both sides, the unweighted heuristic terms and the player
+/// to move
+pub const NUM_INPUTS: usize = 16 + NUM_HEURISTICS + 1;
+
+/// Inputs are counted in seeds. Scale them down so that a freshly initialised
+/// network starts out with small activations.
+const INPUT_SCALE: f32 = 0.1;
+
+pub type Inputs = [f32; NUM_INPUTS];
+
+/// Activations live on the stack, so that evaluating a leaf does not allocate
+pub const MAX_HIDDEN: usize = 64;
+
+pub fn inputs(board: &BoardState, to_move: Position) -> Inputs {
+    let mut inputs = [0.0; NUM_INPUTS];
+    let sides = [board.north, board.south];
+    let features = features(board, to_move);
+    let values = sides
+        .iter()
+        .flat_map(|side| side.pits.iter().chain(std::iter::once(&side.score)))
+        .map(|&n| n as f32)
+        .chain(features.iter().map(|&f| f as f32));
+    for (input, value) in inputs.iter_mut().zip(values) {
+        *input = value * INPUT_SCALE;
+    }
+    inputs[NUM_INPUTS - 1] = match to_move {
+        Position::South => 1.0,
+        Position::North => -1.0,
+    };
+    inputs
+}
+
+/// A multi-layer perceptron with one hidden layer of ReLUs. Its output is an
+/// evaluation in seeds from South's point of view, like `weighted_heuristic`;
+/// `k` times the output is the log-odds of South winning.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Network {
+    pub k: f32,
+    /// `hidden * NUM_INPUTS` weights, one row per hidden unit
+    hidden_weights: Vec<f32>,
+    hidden_biases: Vec<f32>,
+    output_weights: Vec<f32>,
+    output_bias: f32,
+}
+
+impl Network {
+    /// A network with `hidden` units (at most `MAX_HIDDEN`) and small random
+    /// weights
+    pub fn new(hidden: usize, k: f32, seed: u64) -> Self {
+        assert!(hidden <= MAX_HIDDEN, "at most {} hidden units", MAX_HIDDEN);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut uniform = |fan_in: usize, n: usize| -> Vec<f32> {
+            let limit = (6.0 / fan_in as f32).sqrt();
+            (0..n).map(|_| rng.gen_range(-limit..limit)).collect()
+        };
+        Self {
+            k,
+            hidden_weights: uniform(NUM_INPUTS, hidden * NUM_INPUTS),
+            hidden_biases: vec![0.0; hidden],
+            output_weights: uniform(hidden, hidden),
+            output_bias: 0.0,
+        }
+    }
+
+    pub fn hidden(&self) -> usize { self.hidden_biases.len() }
+
+    fn hidden_activations(&self, inputs: &Inputs, activations: &mut [f32]) {
+        for ((activation, row), &bias) in activations
+            .iter_mut()
+            .zip(self.hidden_weights.chunks_exact(NUM_INPUTS))
+            .zip(self.hidden_biases.iter())
+        {
+            let sum: f32 = row.iter().zip(inputs.iter()).map(|(w, x)| w * x).sum();
+            *activation = (sum + bias).max(0.0);
+        }
+    }
+
+    fn output(&self, activations: &[f32]) -> f32 {
+        let sum: f32 = self
+            .output_weights
+            .iter()
+            .zip(activations.iter())
+            .map(|(w, a)| w * a)
+            .sum();
+        sum + self.output_bias
+    }
+
+    /// Evaluation in seeds
+    pub fn forward(&self, inputs: &Inputs) -> f32 {
+        let mut activations = [0.0; MAX_HIDDEN];
+        let activations = &mut activations[..self.hidden()];
+        self.hidden_activations(inputs, activations);
+        self.output(activations)
+    }
+
+    /// Evaluation of a leaf, on the same scale as `weighted_heuristic`. Never
+    /// far enough from a draw to be taken for a forced result, however badly
+    /// the network is trained.
+    pub fn evaluate(&self, board: &BoardState, to_move: Position) -> Score {
+        // the cast saturates, and takes NaN to 0
+        let score = (self.forward(&inputs(board, to_move)) * WEIGHT_SCALE as f32) as Score;
+        score.clamp(-DECISIVE, DECISIVE)
+    }
+
+    /// Take one gradient step on the cross-entropy between the predicted chance
+    /// of South winning and `outcome`. Returns the loss before the step.
+    fn train_step(&mut self, inputs: &Inputs, outcome: f32, learning_rate: f32) -> f32 {
+        let hidden = self.hidden();
+        let mut activations = [0.0; MAX_HIDDEN];
+        let activations = &mut activations[..hidden];
+        self.hidden_activations(inputs, activations);
+        let p = 1.0 / (1.0 + (-self.k * self.output(activations)).exp());
+
+        // derivative of the loss with respect to the output
+        let d_output = self.k * (p - outcome);
+        for (((&activation, output_weight), row), bias) in activations
+            .iter()
+            .zip(self.output_weights.iter_mut())
+            .zip(self.hidden_weights.chunks_exact_mut(NUM_INPUTS))
+            .zip(self.hidden_biases.iter_mut())
+        {
+            let d_hidden = if activation > 0.0 {
+                d_output * *output_weight
+            } else {
+                0.0
+            };
+            *output_weight -= learning_rate * d_output * activation;
+            for (w, x) in row.iter_mut().zip(inputs.iter()) {
+                *w -= learning_rate * d_hidden * x;
+            }
+            *bias -= learning_rate * d_hidden;
+        }
+        self.output_bias -= learning_rate * d_output;
+
+        let p = p.clamp(1e-6, 1.0 - 1e-6);
+        -(outcome * p.ln() + (1.0 - outcome) * (1.0 - p).ln())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|err: String| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> { fs::write(path, self.to_string()) }
+}
+
+/// Train `network` on `samples` with stochastic gradient descent, calling
+/// `on_epoch` with the epoch and the mean loss over it
+pub fn train(
+    network: &mut Network,
+    samples: &[Sample],
+    epochs: usize,
+    learning_rate: f32,
+    seed: u64,
+    mut on_epoch: impl FnMut(usize, f32),
+) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut points: Vec<_> = samples
+        .iter()
+        .map(|sample| (inputs(&sample.board, sample.to_move), sample.outcome))
+        .collect();
+    for epoch in 1..=epochs {
+        points.shuffle(&mut rng);
+        let total: f32 = points
+            .iter()
+            .map(|(inputs, outcome)| network.train_step(inputs, *outcome, learning_rate))
+            .sum();
+        on_epoch(epoch, total / points.len().max(1) as f32);
+    }
+}
+
+/// ```text
+/// mlp inputs=27 hidden=16 k=0.1
+/// <NUM_INPUTS weights then the bias of each hidden unit, one unit per line>
+/// <hidden weights then the bias of the output>
+/// ```
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn line(f: &mut fmt::Formatter<'_>, weights: &[f32], bias: f32) -> fmt::Result {
+            for weight in weights {
+                write!(f, "{} ", weight)?;
+            }
+            writeln!(f, "{}", bias)
+        }
+
+        writeln!(
+            f,
+            "mlp inputs={} hidden={} k={}",
+            NUM_INPUTS,
+            self.hidden(),
+            self.k
+        )?;
+        for (row, &bias) in self
+            .hidden_weights
+            .chunks_exact(NUM_INPUTS)
+            .zip(self.hidden_biases.iter())
+        {
+            line(f, row, bias)?;
+        }
+        line(f, &self.output_weights, self.output_bias)
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        let header = lines.next().unwrap_or("");
+        let (mut inputs, mut hidden, mut k) = (None, None, None);
+        let mut words = header.split_whitespace();
+        if words.next() != Some("mlp") {
+            return Err(format!("expected a header `mlp ...`, got `{}`", header));
+        }
+        for word in words {
+            let mut split = word.splitn(2, '=');
+            match (split.next(), split.next()) {
+                (Some("inputs"), Some(n)) => inputs = n.parse::<usize>().ok(),
+                (Some("hidden"), Some(n)) => hidden = n.parse::<usize>().ok(),
+                (Some("k"), Some(n)) => k = n.parse::<f32>().ok(),
+                _ => return Err(format!("unknown header field `{}`", word)),
+            }
+        }
+        let (hidden, k) = match (inputs, hidden, k) {
+            (Some(NUM_INPUTS), Some(hidden), Some(_)) if hidden > MAX_HIDDEN => {
+                return Err(format!("at most {} hidden units", MAX_HIDDEN))
+            }
+            (Some(NUM_INPUTS), Some(hidden), Some(k)) => (hidden, k),
+            (Some(n), Some(_), Some(_)) => {
+                return Err(format!("network has {} inputs, expected {}", n, NUM_INPUTS))
+            }
+            _ => return Err(format!("incomplete header `{}`", header)),
+        };
+
+        let mut line = |number: usize, expected: usize| -> Result<Vec<f32>, String> {
+            let text = lines
+                .next()
+                .ok_or_else(|| format!("missing line {}", number))?;
+            let values = text
+                .split_whitespace()
+                .map(f32::from_str)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| format!("line {}: {}", number, err))?;
+            if values.len() != expected {
+                return Err(format!(
+                    "line {}: expected {} numbers, got {}",
+                    number,
+                    expected,
+                    values.len()
+                ));
+            }
+            Ok(values)
+        };
+        let mut network = Self {
+            k,
+            hidden_weights: Vec::with_capacity(hidden * NUM_INPUTS),
+            hidden_biases: Vec::with_capacity(hidden),
+            output_weights: Vec::new(),
+            output_bias: 0.0,
+        };
+        for unit in 0..hidden {
+            let mut values = line(unit + 2, NUM_INPUTS + 1)?;
+            network.hidden_biases.push(values.pop().unwrap());
+            network.hidden_weights.extend(values);
+        }
+        let mut values = line(hidden + 2, hidden + 1)?;
+        network.output_bias = values.pop().unwrap();
+        network.output_weights = values;
+        Ok(network)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        eval::{Algorithm, AlphaBeta, Evaluator, Search},
+        game::PlayerConfig,
+        heuristics::{is_decisive, Weights},
+        texel::collect_samples,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn extreme_networks_are_not_forced_results() {
+        let board = BoardState::default();
+        for &bias in &[1e30, -1e30, f32::INFINITY, f32::NEG_INFINITY, f32::NAN] {
+            let mut network = Network::new(4, 0.1, 1);
+            network.output_bias = bias;
+            let score = network.evaluate(&board, Position::South);
+            assert!(!is_decisive(score), "{} scores {}", bias, score);
+
+            let mut search = Search::with_tt_entries(1 << 10);
+            search.network = Some(Arc::new(network));
+            let (_, root) = AlphaBeta::eval(
+                &mut search,
+                board,
+                Position::South,
+                0,
+                true,
+                3,
+                Weights::default(),
+            );
+            assert!(!is_decisive(root), "{} searches to {}", bias, root);
+        }
+    }
+
+    #[test]
+    fn network_round_trip() {
+        let network = Network::new(4, 0.1, 1);
+        let text = network.to_string();
+        assert!(text.starts_with("mlp inputs=27 hidden=4 k=0.1\n"));
+        assert_eq!(text.parse(), Ok(network));
+        assert!("mlp inputs=3 hidden=4 k=0.1".parse::<Network>().is_err());
+        assert!("mlp inputs=27 hidden=1 k=0.1\n1 2 3"
+            .parse::<Network>()
+            .is_err());
+    }
+
+    #[test]
+    fn inputs_are_from_the_board() {
+        let inputs = inputs(&BoardState::default(), Position::North);
+        assert_eq!(inputs[0], 0.7);
+        assert_eq!(inputs[7], 0.0);
+        assert_eq!(inputs[NUM_INPUTS - 1], -1.0);
+    }
+
+    #[test]
+    fn training_reduces_the_loss() {
+        let player = PlayerConfig::new(
+            Algorithm::AlphaBeta,
+            2,
+            "score=1.0 capture=0.6".parse().unwrap(),
+        );
+        let samples = collect_samples(&player, 6, 4, 0).unwrap();
+        let mut network = Network::new(8, 0.1, 0);
+        let mut losses = Vec::new();
+        train(&mut network, &samples, 10, 0.01, 0, |_, loss| {
+            losses.push(loss)
+        });
+        assert_eq!(losses.len(), 10);
+        assert!(losses[9] < losses[0], "{:?}", losses);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{
+    eval::{Algorithm, Search},
+    game::{random_opening, Game, PLAYER_TT_ENTRIES},
+    grammar::ProtocolGrammar,
+    heuristics::{Score, Weights, WEIGHT_SCALE},
+};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SuiteConfig {
+    /// Openings to find
+    pub count: usize,
+    /// Random moves in each opening
+    pub plies: usize,
+    /// Search judging whether an opening is balanced
+    pub algorithm: Algorithm,
+    pub depth: usize,
+    pub weights: Weights,
+    /// Largest evaluation, in seeds, either side of zero that counts as
+    /// balanced
+    pub threshold: f32,
+    /// Give up after this many random openings
+    pub max_attempts: usize,
+    pub seed: u64,
+}
+
+/// Distinct random openings whose shallow-search evaluation is near zero,
+/// with their scores. Fewer than `count` if `max_attempts` run out first, and
+/// an error if no game lasts `plies`.
+pub fn balanced_openings(config: &SuiteConfig) -> Result<Vec<(Game, Score)>, String> {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let threshold = (config.threshold * WEIGHT_SCALE as f32) as Score;
+    let mut openings: Vec<(Game, Score)> = Vec::new();
+    for _ in 0..config.max_attempts {
+        if openings.len() >= config.count {
+            break;
+        }
+        let game = random_opening(&mut rng, config.plies)?;
+        if openings.iter().any(|&(opening, _)| opening == game) {
+            continue;
+        }
+        let mut search = Search::with_tt_entries(PLAYER_TT_ENTRIES);
+        let (_, score) = config.algorithm.eval(
+            &mut search,
+            game.board,
+            game.to_move,
+            0,
+            game.first_move,
+            config.depth,
+            config.weights,
+        );
+        if score.abs() <= threshold {
+            openings.push((game, score));
+        }
+    }
+    Ok(openings)
+}
+
+/// One opening per line: the board in the protocol's format, the player to
+/// move and whether the pie rule can still be used, e.g.
+/// `7,7,7,7,7,7,7,0,0,8,8,8,8,8,8,1 North true`.
+/// Everything after a `#` is a comment.
+pub fn read_openings(path: &Path) -> io::Result<Vec<Game>> {
+    let file = BufReader::new(fs::File::open(path)?);
+    let mut openings = Vec::new();
+    for (idx, line) in file.lines().enumerate() {
+        let line = line?;
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let opening = parse_opening(line).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", idx + 1, err),
+            )
+        })?;
+        openings.push(opening);
+    }
+    Ok(openings)
+}
+
+fn parse_opening(line: &str) -> Result<Game, String> {
+    let words: Vec<_> = line.split_whitespace().collect();
+    match words.as_slice() {
+        [board, to_move, first_move] => Ok(Game {
+            board: ProtocolGrammar::State(board)
+                .map_err(|err| format!("invalid board `{}`: {}", board, err))?,
+            to_move: ProtocolGrammar::Position(to_move)
+                .map_err(|err| format!("invalid player `{}`: {}", to_move, err))?,
+            first_move: first_move
+                .parse()
+                .map_err(|_| format!("expected `true` or `false`, got `{}`", first_move))?,
+        }),
+        _ => Err(format!(
+            "expected `board player first_move`, got `{}`",
+            line
+        )),
+    }
+}
+
+/// Write `openings` in the format `read_openings` reads, each followed by a
+/// comment with its score
+pub fn write_openings(mut out: impl Write, openings: &[(Game, Score)]) -> io::Result<()> {
+    for (game, score) in openings {
+        writeln!(
+            out,
+            "{} {} {} # {}",
+            game.board, game.to_move, game.first_move, score
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> SuiteConfig {
+        SuiteConfig {
+            count: 4,
+            plies: 3,
+            algorithm: Algorithm::AlphaBeta,
+            depth: 2,
+            weights: "score=1".parse().unwrap(),
+            threshold: 2.0,
+            max_attempts: 1000,
+            seed: 5,
+        }
+    }
+
+    #[test]
+    fn openings_are_balanced_and_distinct() {
+        let openings = balanced_openings(&config()).unwrap();
+        assert_eq!(openings.len(), 4);
+        for (idx, &(game, score)) in openings.iter().enumerate() {
+            assert!(score.abs() <= 2 * WEIGHT_SCALE);
+            assert_eq!(game.result(), None);
+            assert!(openings[..idx].iter().all(|&(other, _)| other != game));
+        }
+    }
+
+    #[test]
+    fn openings_round_trip() {
+        let openings = balanced_openings(&config()).unwrap();
+        let path = std::env::temp_dir().join("mankalah_openings_round_trip.txt");
+        let mut text = b"# a comment\n\n".to_vec();
+        write_openings(&mut text, &openings).unwrap();
+        fs::write(&path, text).unwrap();
+        let read = read_openings(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        let games: Vec<_> = openings.iter().map(|&(game, _)| game).collect();
+        assert_eq!(read, games);
+    }
+
+    #[test]
+    fn bad_openings_are_errors() {
+        assert!(parse_opening("7,7,7,7,7,7,7,0,7,7,7,7,7,7,7,0 South true").is_ok());
+        assert!(parse_opening("7,7,7,7,7,7,7,0,7,7,7,7,7,7,7,0 South").is_err());
+        assert!(parse_opening("7,7,7,7,7,7,7,0,7,7,7,7,7,7,7,0 West true").is_err());
+        assert!(parse_opening("7,7,7,7,7,7,7,0,7,7,7,7,7,7,7,0 South maybe").is_err());
+    }
+}
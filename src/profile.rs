@@ -0,0 +1,318 @@
+//! Named agent configurations, shared as a TOML file so that everyone plays
+//! the same agent:
+//!
+//! ```toml
+//! [profile.tournament]
+//! search = "alpha-beta"
+//! network = "agent.network"  # instead of the weights, if given
+//! depth = 9                  # or time = 150, in milliseconds per move
+//! tt_entries = 1_048_576
+//! ponder = true
+//! book = "openings.txt"
+//!
+//! [profile.tournament.weights]
+//! score = 1.0
+//! capture = 0.6
+//! ```
+
+use crate::{
+    eval::Algorithm,
+    game::{Limit, PlayerConfig},
+    heuristics::Weights,
+    nn::Network,
+    tt::DEFAULT_TT_ENTRIES,
+};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use toml::{value::Table, Value};
+
+/// Profiles are read from this file unless told otherwise
+pub const DEFAULT_PROFILES: &str = "profiles.toml";
+
+/// How leaves are evaluated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Eval {
+    /// With the weighted heuristics
+    Linear,
+    /// With a network trained by `train`, from this file
+    Network(PathBuf),
+}
+
+/// Everything that decides how an agent plays. Anything left unset is taken
+/// from somewhere else, see `Profile::or`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub algorithm: Option<Algorithm>,
+    pub eval: Option<Eval>,
+    pub limit: Option<Limit>,
+    pub weights: Option<Weights>,
+    pub tt_entries: Option<usize>,
+    /// Search on the opponent's time
+    pub ponder: Option<bool>,
+    /// Opening book to play from, kept for when the agent can use one
+    pub book: Option<PathBuf>,
+}
+
+impl Profile {
+    /// `self`, with whatever it leaves unset taken from `base`, e.g. the
+    /// command line over a profile over the defaults
+    pub fn or(self, base: Self) -> Self {
+        Self {
+            algorithm: self.algorithm.or(base.algorithm),
+            eval: self.eval.or(base.eval),
+            limit: self.limit.or(base.limit),
+            weights: self.weights.or(base.weights),
+            tt_entries: self.tt_entries.or(base.tt_entries),
+            ponder: self.ponder.or(base.ponder),
+            book: self.book.or(base.book),
+        }
+    }
+
+    /// The in-process player, searching with alpha-beta and evaluating with
+    /// the weighted heuristics unless told otherwise. Loads the network, if
+    /// there is one.
+    pub fn player(&self) -> Result<PlayerConfig, String> {
+        let limit = self.limit.ok_or("no depth or time to search for")?;
+        let weights = self.weights.ok_or("no weights")?;
+        Ok(PlayerConfig {
+            algorithm: self.algorithm(),
+            limit,
+            weights,
+            network: self.network()?,
+        })
+    }
+
+    pub fn algorithm(&self) -> Algorithm { self.algorithm.unwrap_or(Algorithm::AlphaBeta) }
+
+    /// The network that evaluates leaves, if there is one
+    pub fn network(&self) -> Result<Option<Arc<Network>>, String> {
+        match &self.eval {
+            Some(Eval::Network(path)) => Network::load(path)
+                .map(|network| Some(Arc::new(network)))
+                .map_err(|err| format!("cannot read network {}: {}", path.display(), err)),
+            Some(Eval::Linear) | None => Ok(None),
+        }
+    }
+
+    pub fn tt_entries(&self) -> usize { self.tt_entries.unwrap_or(DEFAULT_TT_ENTRIES) }
+}
+
+/// Every profile in a file, by name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profiles(pub BTreeMap<String, Profile>);
+
+impl Profiles {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| text.parse())
+            .map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Profile, String> {
+        self.0.get(name).ok_or_else(|| {
+            let known: Vec<_> = self.0.keys().map(String::as_str).collect();
+            format!(
+                "no profile `{}` (expected one of {})",
+                name,
+                known.join(", ")
+            )
+        })
+    }
+}
+
+impl FromStr for Profiles {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let document: Table = toml::from_str(s).map_err(|err| err.to_string())?;
+        let mut profiles = BTreeMap::new();
+        for (key, value) in document {
+            let tables = match value {
+                Value::Table(tables) if key == "profile" => tables,
+                _ => {
+                    return Err(format!(
+                        "unknown key `{}`, expected [profile.NAME] tables",
+                        key
+                    ))
+                }
+            };
+            for (name, table) in tables {
+                let in_profile = |err: String| format!("profile `{}`: {}", name, err);
+                let table = match table {
+                    Value::Table(table) => table,
+                    _ => return Err(in_profile("should be a [profile.NAME] table".to_owned())),
+                };
+                let mut profile = Profile::default();
+                for (key, value) in &table {
+                    match (key.as_str(), value) {
+                        ("weights", Value::Table(weights)) => {
+                            set_weights(&mut profile, weights).map_err(in_profile)?
+                        }
+                        ("weights", _) => {
+                            return Err(in_profile(
+                                "weights should be a [profile.NAME.weights] table".to_owned(),
+                            ))
+                        }
+                        _ => set_option(&mut profile, key, value).map_err(in_profile)?,
+                    }
+                }
+                profiles.insert(name, profile);
+            }
+        }
+        Ok(Self(profiles))
+    }
+}
+
+fn set_weights(profile: &mut Profile, weights: &Table) -> Result<(), String> {
+    for (name, value) in weights {
+        let weight = match *value {
+            Value::Float(weight) => weight as f32,
+            Value::Integer(weight) => weight as f32,
+            _ => {
+                return Err(format!(
+                    "weight `{}` should be a number, not {}",
+                    name, value
+                ))
+            }
+        };
+        profile
+            .weights
+            .get_or_insert_with(Weights::default)
+            .set(name, weight)
+            .map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+fn set_option(profile: &mut Profile, key: &str, value: &Value) -> Result<(), String> {
+    let invalid = |expected: &str| format!("`{}` should be {}, not {}", key, expected, value);
+    let positive = |value: &Value| match *value {
+        Value::Integer(n) if n > 0 => Ok(n as u64),
+        _ => Err(invalid("a positive integer")),
+    };
+    let path = |value: &Value| match value {
+        Value::String(path) if !path.is_empty() => Ok(PathBuf::from(path)),
+        _ => Err(invalid("a path")),
+    };
+    match key {
+        "search" => match value {
+            Value::String(name) => profile.algorithm = Some(name.parse()?),
+            _ => return Err(invalid("a string")),
+        },
+        "network" => profile.eval = Some(Eval::Network(path(value)?)),
+        "depth" | "time" if profile.limit.is_some() => {
+            return Err("only one of `depth` and `time` can be given".to_owned())
+        }
+        "depth" => profile.limit = Some(Limit::Depth(positive(value)? as usize)),
+        "time" => profile.limit = Some(Limit::Time(Duration::from_millis(positive(value)?))),
+        "tt_entries" => profile.tt_entries = Some(positive(value)? as usize),
+        "ponder" => match *value {
+            Value::Boolean(ponder) => profile.ponder = Some(ponder),
+            _ => return Err(invalid("true or false")),
+        },
+        "threads" => match value {
+            Value::Integer(1) => {}
+            _ => return Err("the search is single-threaded, so `threads` can only be 1".to_owned()),
+        },
+        "book" => profile.book = Some(path(value)?),
+        _ => return Err(format!("unknown option `{}`", key)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PROFILES: &str = r#"
+# shared agent configurations
+[profile.fast]
+search = "mtd-f"
+time = 100      # ms per move
+tt_entries = 65_536
+ponder = true
+book = "openings.txt"
+
+[profile.fast.weights]
+score = 1
+capture = 0.6
+
+[profile."deep"]
+depth = 9
+network = 'deep.network'
+threads = 1
+[profile.deep.weights]
+score = 1.0
+"#;
+
+    #[test]
+    fn parse_profiles() {
+        let profiles: Profiles = PROFILES.parse().unwrap();
+        let fast = profiles.get("fast").unwrap();
+        assert_eq!(fast.algorithm, Some(Algorithm::MtdF));
+        assert_eq!(fast.limit, Some(Limit::Time(Duration::from_millis(100))));
+        assert_eq!(fast.tt_entries, Some(65536));
+        assert_eq!(fast.ponder, Some(true));
+        assert_eq!(fast.weights, Some("score=1 capture=0.6".parse().unwrap()));
+        assert_eq!(fast.eval, None);
+        assert_eq!(fast.book, Some("openings.txt".into()));
+
+        let deep = profiles.get("deep").unwrap();
+        assert_eq!(deep.limit, Some(Limit::Depth(9)));
+        assert_eq!(deep.eval, Some(Eval::Network("deep.network".into())));
+        assert!(profiles.get("shallow").unwrap_err().contains("deep, fast"));
+    }
+
+    #[test]
+    fn unset_options_come_from_the_base() {
+        let profiles: Profiles = PROFILES.parse().unwrap();
+        let command_line = Profile {
+            limit: Some(Limit::Depth(3)),
+            ..Profile::default()
+        };
+        let defaults = Profile {
+            limit: Some(Limit::Depth(8)),
+            tt_entries: Some(1),
+            ..Profile::default()
+        };
+        let profile = command_line
+            .or(profiles.get("fast").unwrap().clone())
+            .or(defaults);
+        assert_eq!(profile.limit, Some(Limit::Depth(3)));
+        assert_eq!(profile.tt_entries, Some(65536));
+
+        let player = profile.player().unwrap();
+        assert_eq!(player.algorithm, Algorithm::MtdF);
+        assert_eq!(player.weights.get("capture"), Some(0.6));
+        assert!(Profile::default().player().is_err());
+    }
+
+    #[test]
+    fn bad_profiles_are_errors() {
+        let error = |text: &str| text.parse::<Profiles>().unwrap_err();
+        assert!(error("depth = 3\n").contains("unknown key `depth`"));
+        assert!(error("[profile.a]\ndepth = 3\ndepth = 4\n").contains("duplicate key"));
+        assert!(error("[profile.a]\ndepth = 3\ntime = 4\n").contains("only one"));
+        assert!(error("[profile.a]\ndepth = -3\n").contains("positive"));
+        assert!(error("[profile.a]\ndepth = 99999999999999999999\n").contains("line 2"));
+        assert!(error("[profile.a]\nsearch = \"greedy\"\n").contains("unknown search"));
+        assert!(error("[profile.a]\nnetwork = 3\n").contains("a path"));
+        assert!(error("[profile.a]\nthreads = 4\n").contains("single-threaded"));
+        assert!(error("[profile.a]\nponder = 1\n").contains("true or false"));
+        assert!(error("[profile.a]\nbook = \"\"\n").contains("a path"));
+        assert!(error("[profile.a]\ncolour = \"red\"\n").contains("unknown option"));
+        assert!(error("[profile.a.weights]\nluck = 1\n").contains("unknown heuristic"));
+        assert!(error("[profile.a.weights]\nscore = \"1\"\n").contains("a number"));
+        assert!(error("[profile.a]\nweights = 1\n").contains("table"));
+        assert!(error("[agent.a]\n").contains("unknown key `agent`"));
+        assert!(error("[profile.a]\n[profile.a]\n").contains("redefinition"));
+        assert!(error("[profile.a]\ndepth = 3 4\n").contains("expected"));
+    }
+}
@@ -1,5 +1,8 @@
 use crate::board::*;
+use std::fmt;
 
+/// Messages sent from the engine to the agent. Displayed as the engine sends
+/// them, newline included.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum EngineMessage {
     NewMatch {
@@ -19,3 +22,138 @@ pub enum Turn {
     Opponent,
     End,
 }
+
+/// Messages sent from the agent to the engine. Displayed as the agent sends
+/// them, newline included.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AgentMessage {
+    /// Sow from pit `n`, counting from 0
+    Move {
+        n: Nat,
+    },
+    Swap,
+}
+
+impl From<PlayerMove> for AgentMessage {
+    fn from(the_move: PlayerMove) -> Self {
+        match the_move {
+            PlayerMove::Move { n } => AgentMessage::Move { n },
+            PlayerMove::Swap => AgentMessage::Swap,
+        }
+    }
+}
+
+impl From<AgentMessage> for PlayerMove {
+    fn from(message: AgentMessage) -> Self {
+        match message {
+            AgentMessage::Move { n } => PlayerMove::Move { n },
+            AgentMessage::Swap => PlayerMove::Swap,
+        }
+    }
+}
+
+impl fmt::Display for AgentMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AgentMessage::Move { n } => writeln!(f, "MOVE;{}", n + 1),
+            AgentMessage::Swap => writeln!(f, "SWAP"),
+        }
+    }
+}
+
+impl fmt::Display for Turn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Turn::You => "YOU",
+            Turn::Opponent => "OPP",
+            Turn::End => "END",
+        })
+    }
+}
+
+impl fmt::Display for EngineMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineMessage::NewMatch { pos } => writeln!(f, "START;{}", pos),
+            EngineMessage::StateChange {
+                player_move,
+                state,
+                turn,
+            } => {
+                write!(f, "CHANGE;")?;
+                match player_move {
+                    // player moves are 1-based
+                    PlayerMove::Move { n } => write!(f, "{}", n + 1)?,
+                    PlayerMove::Swap => write!(f, "SWAP")?,
+                }
+                writeln!(f, ";{};{}", state, turn)
+            }
+            EngineMessage::GameOver => writeln!(f, "END"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::grammar::ProtocolGrammar;
+
+    #[test]
+    fn engine_messages_round_trip() {
+        let state = BoardState::default()
+            .do_move(PlayerMove::Move { n: 2 }, Position::South, true)
+            .0;
+        let messages = [
+            EngineMessage::NewMatch {
+                pos: Position::South,
+            },
+            EngineMessage::NewMatch {
+                pos: Position::North,
+            },
+            EngineMessage::StateChange {
+                player_move: PlayerMove::Move { n: 2 },
+                state,
+                turn: Turn::Opponent,
+            },
+            EngineMessage::StateChange {
+                player_move: PlayerMove::Swap,
+                state,
+                turn: Turn::You,
+            },
+            EngineMessage::StateChange {
+                player_move: PlayerMove::Move { n: 6 },
+                state,
+                turn: Turn::End,
+            },
+            EngineMessage::GameOver,
+        ];
+        for &message in &messages {
+            let text = message.to_string();
+            assert!(text.ends_with('\n'));
+            assert_eq!(ProtocolGrammar::EngineMessage(&text), Ok(message));
+        }
+        assert_eq!(
+            messages[2].to_string(),
+            "CHANGE;3;8,8,7,7,7,7,7,0,7,7,0,8,8,8,8,1;OPP\n"
+        );
+    }
+
+    #[test]
+    fn agent_messages_round_trip() {
+        for n in 0..PITS_PER_PLAYER as Nat {
+            let message = AgentMessage::Move { n };
+            assert_eq!(message.to_string(), format!("MOVE;{}\n", n + 1));
+            assert_eq!(
+                ProtocolGrammar::AgentMessage(&message.to_string()),
+                Ok(message)
+            );
+            assert_eq!(AgentMessage::from(PlayerMove::from(message)), message);
+        }
+        assert_eq!(AgentMessage::Swap.to_string(), "SWAP\n");
+        assert_eq!(
+            ProtocolGrammar::AgentMessage("SWAP\n"),
+            Ok(AgentMessage::Swap)
+        );
+        assert_eq!(PlayerMove::from(AgentMessage::Swap), PlayerMove::Swap);
+    }
+}
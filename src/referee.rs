@@ -0,0 +1,352 @@
+#![allow(non_snake_case)]
+
+//! The log the `ManKalah.jar` referee writes to stderr: every board, every
+//! move and the result of the match.
+//!
+//! The referee names the two sides of the board, and players change sides
+//! when one of them swaps, while `Position` names a player by the side they
+//! started on. Boards and moves are kept as the referee prints them until
+//! `RefereeLog::record` converts them.
+
+use crate::{
+    board::{BoardState, Nat, PlayerMove, PlayerState, Position, PITS_PER_PLAYER},
+    game::{Game, GameRecord, PlayerStats},
+};
+use std::{convert::TryInto, str::FromStr, time::Duration};
+
+/// A move as the referee prints it: from the side of the board it was made on
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RefereeMove {
+    Swap,
+    /// `n` is 0-based, like `PlayerMove::Move`
+    Move {
+        side: Position,
+        n: Nat,
+    },
+}
+
+/// How a player did, as summarised at the end of the log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayerSummary {
+    /// The command the referee ran
+    pub name: String,
+    pub moves: u32,
+    pub ms_per_move: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefereeLog {
+    pub start: BoardState,
+    /// Each move with the board after it. Swapping doesn't change the board,
+    /// so the referee doesn't print it again.
+    pub moves: Vec<(RefereeMove, Option<BoardState>)>,
+    /// Why the match was aborted, if it was
+    pub errors: Vec<String>,
+    /// `None` for a draw
+    pub winner: Option<Position>,
+    /// The winner's seeds minus the loser's, unless the match was aborted
+    pub score: Option<u32>,
+    pub south: PlayerSummary,
+    pub north: PlayerSummary,
+}
+
+impl RefereeLog {
+    pub fn aborted(&self) -> bool { self.score.is_none() }
+
+    /// Replay the game by our rules, checking every board the referee printed
+    pub fn record(&self) -> Result<GameRecord, String> {
+        let start = Game {
+            board: self.start,
+            ..Game::default()
+        };
+        let mut game = start;
+        let mut swapped = false;
+        let mut moves = Vec::new();
+        for (idx, &(referee_move, board)) in self.moves.iter().enumerate() {
+            let the_move = match referee_move {
+                RefereeMove::Swap => PlayerMove::Swap,
+                RefereeMove::Move { side, n } => {
+                    let player = if swapped { !side } else { side };
+                    if player != game.to_move {
+                        return Err(format!("move {}: {} moved out of turn", idx + 1, side));
+                    }
+                    PlayerMove::Move { n }
+                }
+            };
+            if !game.legal_moves().any(|legal| legal == the_move) {
+                return Err(format!("move {}: {:?} is illegal", idx + 1, referee_move));
+            }
+            game.play(the_move);
+            moves.push(the_move);
+            swapped ^= the_move == PlayerMove::Swap;
+
+            let last = idx + 1 == self.moves.len() && !self.aborted();
+            let board_after = if game.result().is_some() || last {
+                game.swept()
+            } else {
+                game.board
+            };
+            let expected = if swapped {
+                BoardState {
+                    north: board_after.south,
+                    south: board_after.north,
+                }
+            } else {
+                board_after
+            };
+            match board {
+                Some(board) if board != expected => {
+                    return Err(format!(
+                        "move {}: the referee's board is {} but we expected {}",
+                        idx + 1,
+                        board,
+                        expected
+                    ))
+                }
+                _ => {}
+            }
+        }
+
+        let margin = match (self.winner, self.score) {
+            (None, _) => 0,
+            (Some(Position::South), Some(score)) => score as i32,
+            (Some(Position::North), Some(score)) => -(score as i32),
+            // aborted, so count what each player had stored
+            (Some(_), None) => game.board.south.score as i32 - game.board.north.score as i32,
+        };
+        let stats = |player: &PlayerSummary| PlayerStats {
+            time: Duration::from_millis(player.ms_per_move * player.moves as u64),
+            nodes: 0,
+        };
+        Ok(GameRecord {
+            start,
+            moves,
+            margin,
+            south: stats(&self.south),
+            north: stats(&self.north),
+        })
+    }
+}
+
+impl FromStr for RefereeLog {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RefereeGrammar::Log(s).map_err(|err| err.to_string())
+    }
+}
+
+/// What the referee says after the last board
+enum Ending {
+    Winner(Position),
+    Draw,
+}
+
+/// The players, in the order the referee lists them (South's at the end of
+/// the match first) and by the side each started on
+fn players(
+    first: (Position, PlayerSummary),
+    second: (Position, PlayerSummary),
+) -> Result<(PlayerSummary, PlayerSummary), &'static str> {
+    match (first, second) {
+        ((Position::South, south), (Position::North, north))
+        | ((Position::North, north), (Position::South, south)) => Ok((south, north)),
+        _ => Err("one summary for each player"),
+    }
+}
+
+peg::parser! {
+    pub grammar RefereeGrammar() for str {
+        rule _() = " "+
+
+        rule digits() -> &'input str
+            = $(['0'..='9']+)
+
+        rule Nat() -> Nat
+            = n: digits() {? n.parse().or(Err("a number of seeds")) }
+
+        rule U32() -> u32
+            = n: digits() {? n.parse().or(Err("a 32-bit number")) }
+
+        rule U64() -> u64
+            = n: digits() {? n.parse().or(Err("a 64-bit number")) }
+
+        /// The players are numbered in the order they were given to the
+        /// referee, so player 1 started South
+        rule Player() -> Position
+            = "1" {Position::South}
+            / "2" {Position::North}
+
+        rule Side() -> Position
+            = "North" {Position::North}
+            / "South" {Position::South}
+
+        /// The command that started an agent, in brackets. Commands can
+        /// contain brackets too, so only the last one counts.
+        rule Name(end: rule<()>) -> String
+            = "(" name: $((!(")" end()) [_])*) ")" end() {name.to_owned()}
+
+        /// North's store and pits from the 7th to the 1st, then South's pits
+        /// and store, so that the pits line up as they do on the board
+        pub rule Board() -> BoardState
+            = north_score: Nat() _ "--" _ north_pits: Nat() ** <{PITS_PER_PLAYER}> _ "\n"
+              south_pits: Nat() ** <{PITS_PER_PLAYER}> _ _ "--" _ south_score: Nat() "\n"
+            {
+                let mut north_pits: [Nat; PITS_PER_PLAYER] = north_pits.try_into().unwrap();
+                north_pits.reverse();
+                BoardState {
+                    north: PlayerState {pits: north_pits, score: north_score},
+                    south: PlayerState {pits: south_pits.try_into().unwrap(), score: south_score},
+                }
+            }
+
+        rule Move() -> (RefereeMove, Option<BoardState>)
+            = "Move: Swap\n" board: Board()? {(RefereeMove::Swap, board)}
+            // holes are 1-based
+            / "Move: " side: Side() " - " n: Nat() "\n" board: Board()
+            {? match n {
+                1..=7 => Ok((RefereeMove::Move {side, n: n - 1}, Some(board))),
+                _ => Err("a hole between 1 and 7"),
+            }}
+
+        rule Error() -> String
+            = "Error: " error: $((!"\n" [_])*) "\n" {error.to_owned()}
+
+        rule Ending() -> Ending
+            = "WINNER: Player " player: Player() " " Name(<"\n">) {Ending::Winner(player)}
+            / "DRAW\n" {Ending::Draw}
+
+        rule Score() -> Option<u32>
+            = "SCORE: " score: U32() "\n" {Some(score)}
+            / "MATCH WAS ABORTED\n" {None}
+
+        rule Summary() -> (Position, PlayerSummary)
+            = "Player " player: Player() " " name: Name(<": ">)
+              moves: U32() " moves, " ms_per_move: U64() " milliseconds per move\n"
+            {(player, PlayerSummary {name, moves, ms_per_move})}
+
+        /// Everything the referee writes to stderr for a match that started
+        pub rule Log() -> RefereeLog
+            = start: Board() moves: Move()* errors: Error()* "\n"
+              ending: Ending() score: Score() "\n"
+              first: Summary() second: Summary() "\n"? ![_]
+            {?
+                let winner = match ending {
+                    Ending::Winner(player) => Some(player),
+                    Ending::Draw => None,
+                };
+                players(first, second).map(|(south, north)| {
+                    RefereeLog {start, moves, errors, winner, score, south, north}
+                })
+            }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const WIN: &str = include_str!("../testdata/referee_win.log");
+    const SWAP: &str = include_str!("../testdata/referee_swap.log");
+    const EARLY_END: &str = include_str!("../testdata/referee_early_end.log");
+
+    #[test]
+    fn parse_win() {
+        let log: RefereeLog = WIN.parse().unwrap();
+        assert_eq!(log.start, BoardState::default());
+        assert_eq!(
+            log.moves[0],
+            (
+                RefereeMove::Move {
+                    side: Position::South,
+                    n: 0
+                },
+                Some(
+                    BoardState::default()
+                        .do_move(PlayerMove::Move { n: 0 }, Position::South, true)
+                        .0
+                )
+            )
+        );
+        assert_eq!(log.winner, Some(Position::North));
+        assert_eq!(log.score, Some(80));
+        assert_eq!(log.south.name, "java -jar MKRefAgent.jar");
+        assert_eq!(log.north.moves, 23);
+        assert_eq!(log.moves.len(), 38);
+
+        let record = log.record().unwrap();
+        assert_eq!(record.margin, -80);
+        assert_eq!(record.positions().last().unwrap().result(), Some(-80));
+        assert_eq!(record.south.time, Duration::from_millis(15));
+    }
+
+    #[test]
+    fn swapped_players_keep_their_names() {
+        let log: RefereeLog = SWAP.parse().unwrap();
+        assert_eq!(log.moves[1], (RefereeMove::Swap, None));
+        // the second player finished South, and the referee lists them first
+        assert_eq!(log.winner, Some(Position::North));
+        assert_eq!(log.north.moves, 47);
+        assert!(log.south.name.contains("--depth 3"));
+
+        let record = log.record().unwrap();
+        assert_eq!(record.moves[1], PlayerMove::Swap);
+        assert_eq!(record.margin, -12);
+        assert_eq!(record.positions().last().unwrap().result(), Some(-12));
+    }
+
+    #[test]
+    fn referee_ends_when_either_side_is_empty() {
+        let log: RefereeLog = EARLY_END.parse().unwrap();
+        let record = log.record().unwrap();
+        assert_eq!(record.margin, -36);
+        // by our rules South could still move
+        assert_eq!(record.positions().last().unwrap().result(), None);
+    }
+
+    #[test]
+    fn aborted_match() {
+        let log = "\
+0  --  7  7  7  7  7  7  7
+7  7  7  7  7  7  7  --  0
+Move: South - 7
+0  --  7  8  8  8  8  8  8
+7  7  7  7  7  7  0  --  1
+Error: Agent ./slow (v2) timed out.
+
+WINNER: Player 1 (./fast)
+MATCH WAS ABORTED
+
+Player 1 (./fast): 1 moves, 3 milliseconds per move
+Player 2 (./slow (v2)): 0 moves, 0 milliseconds per move
+
+";
+        let log: RefereeLog = log.parse().unwrap();
+        assert!(log.aborted());
+        assert_eq!(log.errors, vec!["Agent ./slow (v2) timed out."]);
+        assert_eq!(log.north.name, "./slow (v2)");
+        assert_eq!(log.record().unwrap().margin, 1);
+    }
+
+    #[test]
+    fn bad_logs_are_errors() {
+        assert!("".parse::<RefereeLog>().is_err());
+        // cut short
+        assert!(WIN[..WIN.len() / 2].parse::<RefereeLog>().is_err());
+        assert!(WIN
+            .replace("SCORE: 80", "SCORE: 99999999999")
+            .parse::<RefereeLog>()
+            .is_err());
+        assert!(WIN
+            .replacen("Move: South - 1", "Move: South - 8", 1)
+            .parse::<RefereeLog>()
+            .is_err());
+
+        // parses, but the board doesn't follow from the move
+        let log: RefereeLog = WIN
+            .replacen("Move: South - 1", "Move: South - 2", 1)
+            .parse()
+            .unwrap();
+        assert!(log.record().is_err());
+    }
+}
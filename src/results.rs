@@ -0,0 +1,462 @@
+use crate::{
+    board::Position,
+    game::{GameRecord, MatchResult},
+};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// One game of a benchmark or tournament, from the point of view of the
+/// config being measured
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameResult {
+    /// Git revision the engine was built from
+    pub revision: String,
+    /// Seconds since the Unix epoch when the run started. Tells runs of the
+    /// same revision apart.
+    pub run: u64,
+    pub config: String,
+    pub opponent: String,
+    /// The side `config` played
+    pub side: Position,
+    /// `None` for a draw
+    pub winner: Option<Position>,
+    /// `config`'s final seeds minus the opponent's
+    pub margin: i32,
+    pub our_time_ms: u64,
+    pub their_time_ms: u64,
+    /// Unknown for external agents
+    pub our_nodes: Option<u64>,
+    pub their_nodes: Option<u64>,
+}
+
+impl GameResult {
+    /// A game between in-process players, in which `config` played `side`
+    pub fn from_record(
+        revision: &str,
+        run: u64,
+        config: &str,
+        opponent: &str,
+        side: Position,
+        record: &GameRecord,
+    ) -> Self {
+        let (ours, theirs, margin) = match side {
+            Position::South => (record.south, record.north, record.margin),
+            Position::North => (record.north, record.south, -record.margin),
+        };
+        Self {
+            revision: revision.to_owned(),
+            run,
+            config: config.to_owned(),
+            opponent: opponent.to_owned(),
+            side,
+            winner: winner(record.margin),
+            margin,
+            our_time_ms: ours.time.as_millis() as u64,
+            their_time_ms: theirs.time.as_millis() as u64,
+            our_nodes: Some(ours.nodes),
+            their_nodes: Some(theirs.nodes),
+        }
+    }
+
+    /// The game as a line of JSON
+    pub fn to_json(&self) -> String {
+        fn string(s: &str) -> String {
+            let mut quoted = String::from("\"");
+            for c in s.chars() {
+                match c {
+                    '"' => quoted.push_str("\\\""),
+                    '\\' => quoted.push_str("\\\\"),
+                    c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => quoted.push(c),
+                }
+            }
+            quoted.push('"');
+            quoted
+        }
+        fn number(n: Option<u64>) -> String { n.map_or("null".to_owned(), |n| n.to_string()) }
+
+        let fields = [
+            ("revision", string(&self.revision)),
+            ("run", self.run.to_string()),
+            ("config", string(&self.config)),
+            ("opponent", string(&self.opponent)),
+            ("side", string(&self.side.to_string())),
+            ("winner", string(&WinnerName(self.winner).to_string())),
+            ("margin", self.margin.to_string()),
+            ("our_time_ms", self.our_time_ms.to_string()),
+            ("their_time_ms", self.their_time_ms.to_string()),
+            ("our_nodes", number(self.our_nodes)),
+            ("their_nodes", number(self.their_nodes)),
+        ];
+        let fields: Vec<_> = fields
+            .iter()
+            .map(|(name, value)| format!("\"{}\":{}", name, value))
+            .collect();
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+/// The winner of a game South finished `margin` seeds ahead in
+pub fn winner(margin: i32) -> Option<Position> {
+    match margin {
+        0 => None,
+        m if m > 0 => Some(Position::South),
+        _ => Some(Position::North),
+    }
+}
+
+struct WinnerName(Option<Position>);
+
+impl fmt::Display for WinnerName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(pos) => write!(f, "{}", pos),
+            None => write!(f, "Draw"),
+        }
+    }
+}
+
+pub const CSV_HEADER: &str = "revision,run,config,opponent,side,winner,margin,our_time_ms,\
+                              their_time_ms,our_nodes,their_nodes";
+
+/// Quote a CSV field if it needs it
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+/// Split a CSV line into fields, undoing `csv_field`
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(String::new()),
+            (c, _) => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// A line of CSV, in the order of `CSV_HEADER`
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let number = |n: Option<u64>| n.map_or(String::new(), |n| n.to_string());
+        write!(
+            f,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            csv_field(&self.revision),
+            self.run,
+            csv_field(&self.config),
+            csv_field(&self.opponent),
+            self.side,
+            WinnerName(self.winner),
+            self.margin,
+            self.our_time_ms,
+            self.their_time_ms,
+            number(self.our_nodes),
+            number(self.their_nodes)
+        )
+    }
+}
+
+impl FromStr for GameResult {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = csv_fields(s);
+        if fields.len() != 11 {
+            return Err(format!("expected 11 fields, got {}", fields.len()));
+        }
+        fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("invalid {} `{}`", name, value))
+        }
+        let position = |name: &str, value: &str| match value {
+            "South" => Ok(Position::South),
+            "North" => Ok(Position::North),
+            _ => Err(format!("invalid {} `{}`", name, value)),
+        };
+        let nodes = |name: &str, value: &str| match value {
+            "" => Ok(None),
+            value => parse(name, value).map(Some),
+        };
+        Ok(Self {
+            revision: fields[0].clone(),
+            run: parse("run", &fields[1])?,
+            config: fields[2].clone(),
+            opponent: fields[3].clone(),
+            side: position("side", &fields[4])?,
+            winner: match fields[5].as_str() {
+                "Draw" => None,
+                value => Some(position("winner", value)?),
+            },
+            margin: parse("margin", &fields[6])?,
+            our_time_ms: parse("our_time_ms", &fields[7])?,
+            their_time_ms: parse("their_time_ms", &fields[8])?,
+            our_nodes: nodes("our_nodes", &fields[9])?,
+            their_nodes: nodes("their_nodes", &fields[10])?,
+        })
+    }
+}
+
+pub fn write_csv(mut out: impl Write, results: &[GameResult]) -> io::Result<()> {
+    writeln!(out, "{}", CSV_HEADER)?;
+    for result in results {
+        writeln!(out, "{}", result)?;
+    }
+    Ok(())
+}
+
+pub fn write_json(mut out: impl Write, results: &[GameResult]) -> io::Result<()> {
+    writeln!(out, "[")?;
+    for (idx, result) in results.iter().enumerate() {
+        let comma = if idx + 1 < results.len() { "," } else { "" };
+        writeln!(out, "  {}{}", result.to_json(), comma)?;
+    }
+    writeln!(out, "]")
+}
+
+pub fn read_csv(path: &Path) -> io::Result<Vec<GameResult>> {
+    let text = fs::read_to_string(path)?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && *line != CSV_HEADER)
+        .map(|(idx, line)| {
+            line.parse().map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", idx + 1, err),
+                )
+            })
+        })
+        .collect()
+}
+
+/// `git describe` of the working tree, e.g. `a1b2c3d` or `a1b2c3d-dirty`.
+/// `unknown` outside a git checkout.
+pub fn git_revision() -> String {
+    Command::new("git")
+        .args(["describe", "--always", "--dirty"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|revision| revision.trim().to_owned())
+        .filter(|revision| !revision.is_empty())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// Results of a run are appended to `<dir>/<revision>.csv` and, one object
+/// per line, `<dir>/<revision>.jsonl`
+pub fn history_file(dir: &Path, revision: &str) -> PathBuf { dir.join(format!("{}.csv", revision)) }
+
+pub fn append_history(dir: &Path, results: &[GameResult]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut by_revision: BTreeMap<&str, Vec<&GameResult>> = BTreeMap::new();
+    for result in results {
+        by_revision
+            .entry(&result.revision)
+            .or_default()
+            .push(result);
+    }
+    for (revision, results) in by_revision {
+        let csv_path = history_file(dir, revision);
+        let is_new = !csv_path.exists();
+        let mut csv = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&csv_path)?;
+        if is_new {
+            writeln!(csv, "{}", CSV_HEADER)?;
+        }
+        let mut json = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(csv_path.with_extension("jsonl"))?;
+        for result in results {
+            writeln!(csv, "{}", result)?;
+            writeln!(json, "{}", result.to_json())?;
+        }
+    }
+    Ok(())
+}
+
+/// The games of the most recent run in `results`
+pub fn latest_run(results: Vec<GameResult>) -> Vec<GameResult> {
+    let latest = results.iter().map(|result| result.run).max();
+    results
+        .into_iter()
+        .filter(|result| Some(result.run) == latest)
+        .collect()
+}
+
+/// Totals for one config against one opponent
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub result: MatchResult,
+    pub our_time_ms: u64,
+}
+
+impl Summary {
+    pub fn mean_time_ms(&self) -> f64 {
+        self.our_time_ms as f64 / self.result.games().max(1) as f64
+    }
+
+    pub fn score(&self) -> f64 {
+        let result = &self.result;
+        (result.wins as f64 + result.draws as f64 / 2.0) / result.games().max(1) as f64
+    }
+}
+
+pub fn summarise(results: &[GameResult]) -> BTreeMap<(String, String), Summary> {
+    let mut summaries: BTreeMap<_, Summary> = BTreeMap::new();
+    for result in results {
+        let summary = summaries
+            .entry((result.config.clone(), result.opponent.clone()))
+            .or_default();
+        summary.result.add(result.margin);
+        summary.our_time_ms += result.our_time_ms;
+    }
+    summaries
+}
+
+/// Markdown table of how every config did against every opponent in run `a`
+/// and run `b`
+pub fn compare(a: &[GameResult], b: &[GameResult]) -> String {
+    let (a, b) = (summarise(a), summarise(b));
+    let mut keys: Vec<_> = a.keys().chain(b.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    let mut table = String::new();
+    let columns = [
+        "config",
+        "opponent",
+        "score A",
+        "score B",
+        "margin A",
+        "margin B",
+        "Δ margin",
+        "time A",
+        "time B",
+    ];
+    let rule: Vec<_> = columns
+        .iter()
+        .map(|c| "-".repeat(c.chars().count()))
+        .collect();
+    table.push_str(&format!("| {} |\n", columns.join(" | ")));
+    table.push_str(&format!("| {} |\n", rule.join(" | ")));
+    let cell = |summary: Option<&Summary>, f: &dyn Fn(&Summary) -> String| {
+        summary.map_or("-".to_owned(), f)
+    };
+    for key in keys {
+        let (sa, sb) = (a.get(key), b.get(key));
+        let delta = match (sa, sb) {
+            (Some(sa), Some(sb)) => {
+                format!("{:+.2}", sb.result.mean_margin() - sa.result.mean_margin())
+            }
+            _ => "-".to_owned(),
+        };
+        table.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} | {} | {} | {} |\n",
+            key.0,
+            key.1,
+            cell(sa, &|s| format!("{:.1}%", 100.0 * s.score())),
+            cell(sb, &|s| format!("{:.1}%", 100.0 * s.score())),
+            cell(sa, &|s| format!("{:+.2}", s.result.mean_margin())),
+            cell(sb, &|s| format!("{:+.2}", s.result.mean_margin())),
+            delta,
+            cell(sa, &|s| format!("{:.0}ms", s.mean_time_ms())),
+            cell(sb, &|s| format!("{:.0}ms", s.mean_time_ms())),
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn result(config: &str, margin: i32, run: u64) -> GameResult {
+        GameResult {
+            revision: "abc123".to_owned(),
+            run,
+            config: config.to_owned(),
+            opponent: "java -jar \"Test_Agents/x.jar\", v2".to_owned(),
+            side: Position::North,
+            winner: winner(-margin),
+            margin,
+            our_time_ms: 120,
+            their_time_ms: 80,
+            our_nodes: Some(1000),
+            their_nodes: None,
+        }
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let game = result("depth=4", 6, 1);
+        let line = game.to_string();
+        assert_eq!(
+            line,
+            "abc123,1,depth=4,\"java -jar \"\"Test_Agents/x.jar\"\", \
+             v2\",North,North,6,120,80,1000,"
+        );
+        assert_eq!(line.parse(), Ok(game));
+        assert!("abc123,1,depth=4".parse::<GameResult>().is_err());
+    }
+
+    #[test]
+    fn json_escapes_strings() {
+        let json = result("depth=4", 0, 1).to_json();
+        assert!(json.starts_with("{\"revision\":\"abc123\",\"run\":1,\"config\":\"depth=4\","));
+        assert!(json.contains("\"opponent\":\"java -jar \\\"Test_Agents/x.jar\\\", v2\""));
+        assert!(json.contains("\"winner\":\"Draw\""));
+        assert!(json.ends_with("\"our_nodes\":1000,\"their_nodes\":null}"));
+    }
+
+    #[test]
+    fn history_appends_and_compares() {
+        let dir = std::env::temp_dir().join(format!("mankalah_history_{}", std::process::id()));
+        append_history(&dir, &[result("a", 2, 1), result("b", -4, 1)]).unwrap();
+        append_history(&dir, &[result("a", 6, 2)]).unwrap();
+        let history = read_csv(&history_file(&dir, "abc123")).unwrap();
+        let jsonl = fs::read_to_string(dir.join("abc123.jsonl")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(jsonl.lines().count(), 3);
+
+        let latest = latest_run(history.clone());
+        assert_eq!(latest, vec![result("a", 6, 2)]);
+
+        let table = compare(&history[..2], &latest);
+        assert!(table.contains("| a | "));
+        assert!(table.contains("| +2.00 | +6.00 | +4.00 |"));
+        assert!(table.contains("| b | "));
+    }
+}
@@ -0,0 +1,207 @@
+use crate::game::MatchResult;
+use std::fmt;
+
+/// Elo difference that gives an expected score of `score` (between 0 and 1)
+pub fn elo(score: f64) -> f64 { -400.0 * (1.0 / score - 1.0).log10() }
+
+/// Expected score of a player `elo` points stronger than their opponent
+pub fn expected_score(elo: f64) -> f64 { 1.0 / (1.0 + 10f64.powf(-elo / 400.0)) }
+
+/// Elo difference measured by a match, with a 95% confidence interval
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EloEstimate {
+    pub elo: f64,
+    /// Half the width of the confidence interval
+    pub error: f64,
+}
+
+impl EloEstimate {
+    pub fn new(result: &MatchResult) -> Self {
+        let games = result.games() as f64;
+        if games == 0.0 {
+            return Self {
+                elo: 0.0,
+                error: f64::INFINITY,
+            };
+        }
+        let (score, variance) = score_moments(result);
+        if score == 0.0 || score == 1.0 {
+            // a clean sweep says nothing about how much stronger the winner is
+            return Self {
+                elo: elo(score),
+                error: f64::INFINITY,
+            };
+        }
+        // 1.96 standard errors either side of the mean
+        let spread = 1.96 * (variance / games).sqrt();
+        let clamp = |score: f64| score.clamp(1e-3, 1.0 - 1e-3);
+        Self {
+            elo: elo(clamp(score)),
+            error: (elo(clamp(score + spread)) - elo(clamp(score - spread))) / 2.0,
+        }
+    }
+}
+
+impl fmt::Display for EloEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // adding zero turns -0.0 into 0.0
+        write!(f, "{:+.1} ± {:.1}", self.elo + 0.0, self.error)
+    }
+}
+
+/// Mean and variance of the score per game
+fn score_moments(result: &MatchResult) -> (f64, f64) {
+    let games = result.games() as f64;
+    let score = (result.wins as f64 + result.draws as f64 / 2.0) / games;
+    let variance = (result.wins as f64 * (1.0 - score).powi(2)
+        + result.draws as f64 * (0.5 - score).powi(2)
+        + result.losses as f64 * score.powi(2))
+        / games;
+    (score, variance)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SprtDecision {
+    /// The candidate is no better than `elo0`
+    AcceptH0,
+    /// The candidate is at least `elo1` better
+    AcceptH1,
+    Continue,
+}
+
+/// Sequential probability ratio test of whether a candidate is `elo0` or
+/// `elo1` points stronger than a baseline, with false positive rate `alpha`
+/// and false negative rate `beta`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    /// Log-likelihood ratio below which H0 is accepted
+    pub fn lower_bound(&self) -> f64 { (self.beta / (1.0 - self.alpha)).ln() }
+
+    /// Log-likelihood ratio above which H1 is accepted
+    pub fn upper_bound(&self) -> f64 { ((1.0 - self.beta) / self.alpha).ln() }
+
+    /// Log-likelihood ratio of H1 to H0 given the candidate's results, using
+    /// the normal approximation to the distribution of the mean score. The
+    /// variance is estimated as if there were one more win and one more loss,
+    /// so that a match with only one kind of result, such as a clean sweep,
+    /// still has some spread and can be decided.
+    pub fn llr(&self, result: &MatchResult) -> f64 {
+        if result.games() == 0 {
+            return 0.0;
+        }
+        let (score, _) = score_moments(result);
+        let (_, variance) = score_moments(&MatchResult {
+            wins: result.wins + 1,
+            losses: result.losses + 1,
+            ..*result
+        });
+        let (s0, s1) = (expected_score(self.elo0), expected_score(self.elo1));
+        result.games() as f64 * (s1 - s0) * (2.0 * score - s0 - s1) / (2.0 * variance)
+    }
+
+    pub fn decide(&self, result: &MatchResult) -> SprtDecision {
+        let llr = self.llr(result);
+        if llr >= self.upper_bound() {
+            SprtDecision::AcceptH1
+        } else if llr <= self.lower_bound() {
+            SprtDecision::AcceptH0
+        } else {
+            SprtDecision::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn result(wins: u32, draws: u32, losses: u32) -> MatchResult {
+        MatchResult {
+            wins,
+            draws,
+            losses,
+            total_margin: 0,
+        }
+    }
+
+    #[test]
+    fn elo_and_expected_score_are_inverses() {
+        assert_eq!(elo(0.5), 0.0);
+        for &score in &[0.1, 0.3, 0.64, 0.9] {
+            assert!((expected_score(elo(score)) - score).abs() < 1e-9);
+        }
+        assert!((elo(0.75) - 190.8).abs() < 0.1);
+    }
+
+    #[test]
+    fn more_games_give_smaller_error_bars() {
+        let few = EloEstimate::new(&result(6, 2, 2));
+        let many = EloEstimate::new(&result(60, 20, 20));
+        assert!((few.elo - many.elo).abs() < 1e-9);
+        assert!(few.elo > 0.0);
+        assert!(many.error < few.error);
+    }
+
+    #[test]
+    fn even_matches_are_centred_on_zero() {
+        let even = EloEstimate::new(&result(10, 5, 10));
+        assert!(even.elo.abs() < 1e-9);
+        assert!(even.error > 0.0);
+        assert!(EloEstimate::new(&result(0, 0, 0)).error.is_infinite());
+        let sweep = EloEstimate::new(&result(10, 0, 0));
+        assert_eq!(sweep.to_string(), "+inf ± inf");
+    }
+
+    fn sprt() -> Sprt {
+        Sprt {
+            elo0: 0.0,
+            elo1: 20.0,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+
+    #[test]
+    fn bounds() {
+        assert!((sprt().upper_bound() - 2.944).abs() < 1e-3);
+        assert!((sprt().lower_bound() + 2.944).abs() < 1e-3);
+    }
+
+    #[test]
+    fn clear_results_are_decided() {
+        assert_eq!(
+            sprt().decide(&result(600, 100, 300)),
+            SprtDecision::AcceptH1
+        );
+        assert_eq!(
+            sprt().decide(&result(300, 100, 600)),
+            SprtDecision::AcceptH0
+        );
+        assert_eq!(sprt().decide(&result(6, 1, 3)), SprtDecision::Continue);
+        assert_eq!(sprt().decide(&result(0, 0, 0)), SprtDecision::Continue);
+    }
+
+    #[test]
+    fn one_sided_results_are_decided() {
+        assert_eq!(sprt().decide(&result(100, 0, 0)), SprtDecision::AcceptH1);
+        assert_eq!(sprt().decide(&result(0, 0, 100)), SprtDecision::AcceptH0);
+        // no better than the baseline
+        assert_eq!(sprt().decide(&result(0, 100, 0)), SprtDecision::AcceptH0);
+        assert_eq!(sprt().decide(&result(1, 0, 0)), SprtDecision::Continue);
+    }
+
+    #[test]
+    fn llr_grows_with_evidence() {
+        let few = sprt().llr(&result(60, 10, 30));
+        let many = sprt().llr(&result(600, 100, 300));
+        assert!(few > 0.0);
+        assert!(many > few);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    board::Position,
+    game::{random_opening, Game},
+    heuristics::{features, Weights, NUM_HEURISTICS},
+    texel::outcome,
+};
+use rand::{rngs::StdRng, seq::IteratorRandom, Rng, SeedableRng};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TdConfig {
+    /// Learning rate
+    pub alpha: f32,
+    /// How much of each update is passed back to earlier positions: 0 only
+    /// updates towards the next position, 1 towards the final outcome
+    pub lambda: f32,
+    /// Chance of playing a random move instead of the best one
+    pub epsilon: f32,
+    /// Scale of the evaluation, in seeds, before it is squashed into a chance
+    /// of winning
+    pub k: f32,
+    /// Random moves at the start of every game
+    pub opening_plies: usize,
+    /// Which weights can change. The others stay as they started.
+    pub tuned: [bool; NUM_HEURISTICS],
+    pub seed: u64,
+}
+
+/// Chance that South wins from `game`, and its gradient with respect to the
+/// weights. Finished games are worth their outcome and have no gradient.
+fn value(weights: &Weights, k: f32, game: &Game) -> (f32, [f32; NUM_HEURISTICS]) {
+    let mut gradient = [0.0; NUM_HEURISTICS];
+    if let Some(margin) = game.result() {
+        return (outcome(margin), gradient);
+    }
+    let features = features(&game.board, game.to_move);
+    let eval: f32 = weights
+        .0
+        .iter()
+        .zip(features.iter())
+        .map(|(&w, &f)| w * f as f32)
+        .sum();
+    let v = 1.0 / (1.0 + (-k * eval).exp());
+    for (g, &f) in gradient.iter_mut().zip(features.iter()) {
+        *g = v * (1.0 - v) * k * f as f32;
+    }
+    (v, gradient)
+}
+
+/// Learns linear weights over the heuristic features by playing itself,
+/// choosing among `child_boards` by their value
+#[derive(Debug, Clone)]
+pub struct Learner {
+    pub weights: Weights,
+    pub games: usize,
+    config: TdConfig,
+    rng: StdRng,
+}
+
+impl Learner {
+    pub fn new(config: TdConfig, initial: Weights) -> Self {
+        Self {
+            weights: initial,
+            games: 0,
+            config,
+            rng: StdRng::seed_from_u64(config.seed),
+        }
+    }
+
+    /// The child of `game` with the best value for the player to move, or a
+    /// random one with probability `epsilon`
+    fn choose(&mut self, game: &Game) -> Game {
+        let children = game.board.child_boards(game.to_move, game.first_move).map(
+            |(_, board, to_move, first_move)| Game {
+                board,
+                to_move,
+                first_move,
+            },
+        );
+        if self.rng.gen::<f32>() < self.config.epsilon {
+            return children.choose(&mut self.rng).unwrap();
+        }
+        let (weights, k) = (self.weights, self.config.k);
+        let scored = children.map(|child| (value(&weights, k, &child).0, child));
+        let best = match game.to_move {
+            Position::South => scored.max_by(|a, b| a.0.partial_cmp(&b.0).unwrap()),
+            Position::North => scored.min_by(|a, b| a.0.partial_cmp(&b.0).unwrap()),
+        };
+        best.unwrap().1
+    }
+
+    /// Play one game, updating the weights after every move. Returns South's
+    /// final seeds minus North's, or an error if no game lasts
+    /// `opening_plies`.
+    pub fn play_game(&mut self) -> Result<i32, String> {
+        let config = self.config;
+        let mut game = random_opening(&mut self.rng, config.opening_plies)?;
+        let mut traces = [0.0; NUM_HEURISTICS];
+        loop {
+            if let Some(margin) = game.result() {
+                self.games += 1;
+                return Ok(margin);
+            }
+            let (v, gradient) = value(&self.weights, config.k, &game);
+            for (trace, g) in traces.iter_mut().zip(gradient.iter()) {
+                *trace = config.lambda * *trace + g;
+            }
+            let next = self.choose(&game);
+            let (next_v, _) = value(&self.weights, config.k, &next);
+            let delta = next_v - v;
+            for ((w, trace), &tuned) in self
+                .weights
+                .0
+                .iter_mut()
+                .zip(traces.iter())
+                .zip(config.tuned.iter())
+            {
+                if tuned {
+                    *w += config.alpha * delta * trace;
+                }
+            }
+            game = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heuristics::heuristic_index;
+
+    fn config() -> TdConfig {
+        let mut tuned = [false; NUM_HEURISTICS];
+        tuned[heuristic_index("score").unwrap()] = true;
+        tuned[heuristic_index("capture").unwrap()] = true;
+        TdConfig {
+            alpha: 0.1,
+            lambda: 0.7,
+            epsilon: 0.1,
+            k: 0.1,
+            opening_plies: 2,
+            tuned,
+            seed: 3,
+        }
+    }
+
+    #[test]
+    fn finished_games_are_worth_their_outcome() {
+        let mut game = Game::default();
+        game.board.south.pits = [0; 7];
+        game.board.south.score = 49;
+        game.board.north.pits = [0, 0, 0, 0, 0, 0, 49];
+        game.to_move = Position::South;
+        let weights: Weights = "score=1".parse().unwrap();
+        assert_eq!(value(&weights, 0.1, &game), (0.5, [0.0; NUM_HEURISTICS]));
+    }
+
+    #[test]
+    fn only_tuned_weights_are_learned() {
+        let initial: Weights = "score=0.5 chaining=0.3".parse().unwrap();
+        let mut learner = Learner::new(config(), initial);
+        for _ in 0..5 {
+            learner.play_game().unwrap();
+        }
+        assert_eq!(learner.games, 5);
+        assert_ne!(learner.weights.get("score"), initial.get("score"));
+        assert_eq!(learner.weights.get("chaining"), initial.get("chaining"));
+        assert_eq!(learner.weights.get("hoarding"), Some(0.0));
+    }
+
+    #[test]
+    fn learning_is_deterministic_for_a_seed() {
+        let initial: Weights = "score=0.5".parse().unwrap();
+        let mut a = Learner::new(config(), initial);
+        let mut b = Learner::new(config(), initial);
+        for _ in 0..3 {
+            assert_eq!(a.play_game(), b.play_game());
+        }
+        assert_eq!(a.weights, b.weights);
+    }
+}
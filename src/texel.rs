@@ -0,0 +1,309 @@
+use crate::{
+    board::{BoardState, Position},
+    game::{play_game, random_opening, PlayerConfig},
+    grammar::ProtocolGrammar,
+    heuristics::{features, Weights, NUM_HEURISTICS},
+};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{
+    fmt, fs,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    str::FromStr,
+};
+
+/// A position from a game and how that game ended
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sample {
+    pub board: BoardState,
+    pub to_move: Position,
+    /// 1 if South won, 0.5 for a draw and 0 if North won
+    pub outcome: f32,
+}
+
+/// `outcome` of every position of a game South finished `margin` seeds ahead
+pub fn outcome(margin: i32) -> f32 {
+    match margin {
+        0 => 0.5,
+        m if m > 0 => 1.0,
+        _ => 0.0,
+    }
+}
+
+/// One sample per line: the board in the protocol's format, the player to
+/// move and the outcome, e.g.
+/// `7,7,7,7,7,7,7,0,7,7,7,7,7,7,7,0 South 0.5`
+impl fmt::Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.board, self.to_move, self.outcome)
+    }
+}
+
+impl FromStr for Sample {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<_> = s.split_whitespace().collect();
+        match words.as_slice() {
+            [board, to_move, outcome] => Ok(Self {
+                board: ProtocolGrammar::State(board)
+                    .map_err(|err| format!("invalid board `{}`: {}", board, err))?,
+                to_move: ProtocolGrammar::Position(to_move)
+                    .map_err(|err| format!("invalid player `{}`: {}", to_move, err))?,
+                outcome: match outcome.parse() {
+                    Ok(outcome) if (0.0..=1.0).contains(&outcome) => outcome,
+                    _ => return Err(format!("invalid outcome `{}`", outcome)),
+                },
+            }),
+            _ => Err(format!("expected `board player outcome`, got `{}`", s)),
+        }
+    }
+}
+
+pub fn read_samples(path: &Path) -> io::Result<Vec<Sample>> {
+    let file = BufReader::new(fs::File::open(path)?);
+    let mut samples = Vec::new();
+    for (idx, line) in file.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let sample = line.parse().map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", idx + 1, err),
+            )
+        })?;
+        samples.push(sample);
+    }
+    Ok(samples)
+}
+
+pub fn write_samples(mut out: impl Write, samples: &[Sample]) -> io::Result<()> {
+    for sample in samples {
+        writeln!(out, "{}", sample)?;
+    }
+    Ok(())
+}
+
+/// Every position of `games` self-play games by `player`, each starting with
+/// `opening_plies` random moves. Finished positions are left out: they need no
+/// evaluation. An error if no game lasts `opening_plies`.
+pub fn collect_samples(
+    player: &PlayerConfig,
+    games: usize,
+    opening_plies: usize,
+    seed: u64,
+) -> Result<Vec<Sample>, String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut samples = Vec::new();
+    for _ in 0..games {
+        let record = play_game(player, player, random_opening(&mut rng, opening_plies)?);
+        let outcome = outcome(record.margin);
+        samples.extend(
+            record
+                .positions()
+                .filter(|game| game.result().is_none())
+                .map(|game| Sample {
+                    board: game.board,
+                    to_move: game.to_move,
+                    outcome,
+                }),
+        );
+    }
+    Ok(samples)
+}
+
+/// A sample, ready for fitting
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Point {
+    features: [f32; NUM_HEURISTICS],
+    outcome: f32,
+}
+
+fn points(samples: &[Sample]) -> Vec<Point> {
+    samples
+        .iter()
+        .map(|sample| {
+            let mut point = Point {
+                features: [0.0; NUM_HEURISTICS],
+                outcome: sample.outcome,
+            };
+            let values = features(&sample.board, sample.to_move);
+            for (feature, &value) in point.features.iter_mut().zip(values.iter()) {
+                *feature = value as f32;
+            }
+            point
+        })
+        .collect()
+}
+
+/// Predicted chance that South wins: the logistic function of `k` times the
+/// evaluation in seeds
+fn predict(weights: &Weights, k: f32, point: &Point) -> f32 {
+    let eval: f32 = weights
+        .0
+        .iter()
+        .zip(point.features.iter())
+        .map(|(w, f)| w * f)
+        .sum();
+    1.0 / (1.0 + (-k * eval).exp())
+}
+
+/// Mean cross-entropy between predictions and outcomes
+fn mean_loss(weights: &Weights, k: f32, points: &[Point]) -> f32 {
+    // keep `ln` finite when a prediction saturates
+    const EPSILON: f32 = 1e-6;
+    let total: f32 = points
+        .iter()
+        .map(|point| {
+            let p = predict(weights, k, point).clamp(EPSILON, 1.0 - EPSILON);
+            -(point.outcome * p.ln() + (1.0 - point.outcome) * (1.0 - p).ln())
+        })
+        .sum();
+    total / points.len().max(1) as f32
+}
+
+pub fn loss(weights: &Weights, k: f32, samples: &[Sample]) -> f32 {
+    mean_loss(weights, k, &points(samples))
+}
+
+/// Scale of the evaluation that best predicts the outcomes for `weights`,
+/// found by golden-section search on a log scale. Weights and `k` are
+/// interchangeable, so `k` is fixed before tuning and the weights keep the
+/// scale of the evaluation they started with.
+pub fn fit_k(weights: &Weights, samples: &[Sample]) -> f32 {
+    let points = points(samples);
+    let loss_at = |log_k: f32| mean_loss(weights, log_k.exp(), &points);
+    let ratio = (5f32.sqrt() - 1.0) / 2.0;
+    let (mut lo, mut hi) = ((1e-4f32).ln(), (10f32).ln());
+    for _ in 0..60 {
+        let a = hi - ratio * (hi - lo);
+        let b = lo + ratio * (hi - lo);
+        if loss_at(a) < loss_at(b) {
+            hi = b;
+        } else {
+            lo = a;
+        }
+    }
+    ((lo + hi) / 2.0).exp()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TexelConfig {
+    pub iterations: usize,
+    pub learning_rate: f32,
+    /// Which weights can change. The others stay as they started.
+    pub tuned: [bool; NUM_HEURISTICS],
+}
+
+/// Minimise the loss over `samples` with Adam, starting from `initial` with
+/// the evaluation scaled by `k`. Calls `on_iteration` with the iteration,
+/// weights and loss after every step.
+pub fn tune(
+    config: &TexelConfig,
+    initial: Weights,
+    k: f32,
+    samples: &[Sample],
+    mut on_iteration: impl FnMut(usize, &Weights, f32),
+) -> Weights {
+    const BETA1: f32 = 0.9;
+    const BETA2: f32 = 0.999;
+    const EPSILON: f32 = 1e-8;
+
+    let points = points(samples);
+    let n = points.len().max(1) as f32;
+    let mut weights = initial;
+    let mut m = [0.0; NUM_HEURISTICS];
+    let mut v = [0.0; NUM_HEURISTICS];
+    for iteration in 1..=config.iterations {
+        // derivative of the cross-entropy of the logistic function
+        let mut gradient = [0.0; NUM_HEURISTICS];
+        for point in &points {
+            let error = predict(&weights, k, point) - point.outcome;
+            for (g, f) in gradient.iter_mut().zip(point.features.iter()) {
+                *g += error * k * f / n;
+            }
+        }
+
+        let t = iteration as i32;
+        for i in 0..NUM_HEURISTICS {
+            if !config.tuned[i] {
+                continue;
+            }
+            m[i] = BETA1 * m[i] + (1.0 - BETA1) * gradient[i];
+            v[i] = BETA2 * v[i] + (1.0 - BETA2) * gradient[i] * gradient[i];
+            let m_hat = m[i] / (1.0 - BETA1.powi(t));
+            let v_hat = v[i] / (1.0 - BETA2.powi(t));
+            weights.0[i] -= config.learning_rate * m_hat / (v_hat.sqrt() + EPSILON);
+        }
+        on_iteration(iteration, &weights, mean_loss(&weights, k, &points));
+    }
+    weights
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{eval::Algorithm, heuristics::heuristic_index};
+
+    fn player() -> PlayerConfig {
+        PlayerConfig::new(
+            Algorithm::AlphaBeta,
+            2,
+            "score=1.0 capture=0.6".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn sample_round_trip() {
+        let sample = Sample {
+            board: BoardState::default(),
+            to_move: Position::North,
+            outcome: 0.5,
+        };
+        assert_eq!(
+            sample.to_string(),
+            "7,7,7,7,7,7,7,0,7,7,7,7,7,7,7,0 North 0.5"
+        );
+        assert_eq!(sample.to_string().parse(), Ok(sample));
+        assert!("7,7,7,7,7,7,7,0,7,7,7,7,7,7,7,0 North 2"
+            .parse::<Sample>()
+            .is_err());
+        assert!("7,7,7,7,7,7,7,0 North 1".parse::<Sample>().is_err());
+    }
+
+    #[test]
+    fn samples_take_the_outcome_of_their_game() {
+        let samples = collect_samples(&player(), 2, 4, 0).unwrap();
+        assert!(!samples.is_empty());
+        for sample in &samples {
+            assert!([0.0, 0.5, 1.0].contains(&sample.outcome));
+        }
+    }
+
+    #[test]
+    fn tuning_reduces_the_loss() {
+        let samples = collect_samples(&player(), 6, 4, 0).unwrap();
+        let initial: Weights = "score=0.1".parse().unwrap();
+        let k = fit_k(&initial, &samples);
+        let mut tuned = [false; NUM_HEURISTICS];
+        tuned[heuristic_index("score").unwrap()] = true;
+        tuned[heuristic_index("capture").unwrap()] = true;
+        let config = TexelConfig {
+            iterations: 50,
+            learning_rate: 0.01,
+            tuned,
+        };
+        let mut losses = Vec::new();
+        let weights = tune(&config, initial, k, &samples, |_, _, loss| {
+            losses.push(loss)
+        });
+        assert_eq!(losses.len(), 50);
+        assert!(loss(&weights, k, &samples) < loss(&initial, k, &samples));
+        assert_eq!(
+            weights.get("chaining").unwrap(),
+            initial.get("chaining").unwrap()
+        );
+    }
+}
@@ -0,0 +1,357 @@
+use crate::{
+    board::Position,
+    eval::Algorithm,
+    game::{play_match, play_match_with, Game, GameRecord, Limit, MatchResult, PlayerConfig},
+    nn::Network,
+    results::GameResult,
+    stats::{EloEstimate, Sprt, SprtDecision},
+};
+use std::{fmt, num::NonZeroUsize, path::Path, str::FromStr, sync::Arc, time::Duration};
+
+/// A named player in a tournament
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entrant {
+    pub name: String,
+    pub player: PlayerConfig,
+}
+
+/// `name;key=value;...`, where the keys are `search`, `depth`, `time` (in
+/// milliseconds per move), `weights` and `network` (a file), e.g.
+/// `deep;search=mtd-f;depth=8;weights=score=1 capture=0.6`. Unless given, the
+/// search is alpha-beta to depth 4 with `score=1`.
+impl FromStr for Entrant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split(';');
+        let name = fields.next().unwrap_or("").trim();
+        if name.is_empty() || name.contains('=') {
+            return Err(format!("agent `{}` should start with its name", s));
+        }
+        let mut player = PlayerConfig::new(Algorithm::AlphaBeta, 4, "score=1".parse().unwrap());
+        for field in fields {
+            let mut split = field.splitn(2, '=');
+            let (key, value) = match (split.next(), split.next()) {
+                (Some(key), Some(value)) => (key.trim(), value.trim()),
+                _ => return Err(format!("expected `key=value`, got `{}`", field)),
+            };
+            let invalid = |err: &dyn fmt::Display| format!("invalid {} `{}`: {}", key, value, err);
+            match key {
+                "search" => player.algorithm = value.parse().map_err(|err| invalid(&err))?,
+                "depth" => {
+                    let depth: NonZeroUsize = value.parse().map_err(|err| invalid(&err))?;
+                    player.limit = Limit::Depth(depth.get());
+                }
+                "time" => {
+                    let millis = value.parse().map_err(|err| invalid(&err))?;
+                    player.limit = Limit::Time(Duration::from_millis(millis));
+                }
+                "weights" => player.weights = value.parse().map_err(|err| invalid(&err))?,
+                "network" => {
+                    let network = Network::load(Path::new(value)).map_err(|err| invalid(&err))?;
+                    player.network = Some(Arc::new(network));
+                }
+                _ => return Err(format!("unknown agent option `{}`", key)),
+            }
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            player,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// Every entrant plays every other
+    RoundRobin,
+    /// The first entrant plays every other
+    Gauntlet,
+}
+
+impl Format {
+    pub const NAMES: [&'static str; 2] = ["round-robin", "gauntlet"];
+
+    /// Indices of the entrants in every match
+    pub fn pairings(self, entrants: usize) -> Vec<(usize, usize)> {
+        match self {
+            Self::RoundRobin => (0..entrants)
+                .flat_map(|a| (a + 1..entrants).map(move |b| (a, b)))
+                .collect(),
+            Self::Gauntlet => (1..entrants).map(|b| (0, b)).collect(),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round-robin" => Ok(Self::RoundRobin),
+            "gauntlet" => Ok(Self::Gauntlet),
+            _ => Err(format!("expected one of {:?}", Self::NAMES)),
+        }
+    }
+}
+
+/// A match between two entrants, from the first one's point of view
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Pairing {
+    pub first: usize,
+    pub second: usize,
+    pub result: MatchResult,
+}
+
+/// Play every match of the tournament, each from all of `openings` with both
+/// colours, calling `on_pairing` as each one finishes
+pub fn run(
+    entrants: &[Entrant],
+    format: Format,
+    openings: &[Game],
+    mut on_pairing: impl FnMut(&Pairing),
+) -> Tournament {
+    let mut games = Vec::new();
+    let pairings = format
+        .pairings(entrants.len())
+        .into_iter()
+        .map(|(first, second)| {
+            let result = play_match_with(
+                &entrants[first].player,
+                &entrants[second].player,
+                openings,
+                |side, record| {
+                    games.push(TournamentGame {
+                        first,
+                        second,
+                        side,
+                        record: record.clone(),
+                    })
+                },
+            );
+            let pairing = Pairing {
+                first,
+                second,
+                result,
+            };
+            on_pairing(&pairing);
+            pairing
+        })
+        .collect();
+    Tournament {
+        names: entrants
+            .iter()
+            .map(|entrant| entrant.name.clone())
+            .collect(),
+        pairings,
+        games,
+    }
+}
+
+/// A game between the entrants of a pairing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TournamentGame {
+    pub first: usize,
+    pub second: usize,
+    /// The side the first entrant played
+    pub side: Position,
+    pub record: GameRecord,
+}
+
+/// Results of a finished tournament
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tournament {
+    pub names: Vec<String>,
+    pub pairings: Vec<Pairing>,
+    pub games: Vec<TournamentGame>,
+}
+
+impl Tournament {
+    /// Every game, from the first entrant of its pairing's point of view
+    pub fn results(&self, revision: &str, run: u64) -> Vec<GameResult> {
+        self.games
+            .iter()
+            .map(|game| {
+                GameResult::from_record(
+                    revision,
+                    run,
+                    &self.names[game.first],
+                    &self.names[game.second],
+                    game.side,
+                    &game.record,
+                )
+            })
+            .collect()
+    }
+
+    /// Every game of each entrant, best first by points
+    pub fn standings(&self) -> Vec<(&str, MatchResult)> {
+        let mut totals = vec![MatchResult::default(); self.names.len()];
+        for pairing in &self.pairings {
+            totals[pairing.first].merge(&pairing.result);
+            totals[pairing.second].merge(&pairing.result.reversed());
+        }
+        let mut standings: Vec<_> = self.names.iter().map(String::as_str).zip(totals).collect();
+        standings.sort_by_key(|(_, result)| {
+            std::cmp::Reverse((2 * result.wins + result.draws, result.total_margin))
+        });
+        standings
+    }
+}
+
+/// Markdown tables of every match and of the standings. Elo is relative to
+/// the opponents that were played.
+impl fmt::Display for Tournament {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "| player | opponent | W/D/L | mean margin | Elo |")?;
+        writeln!(f, "|--------|----------|-------|-------------|-----|")?;
+        for pairing in &self.pairings {
+            let result = &pairing.result;
+            writeln!(
+                f,
+                "| {} | {} | {}/{}/{} | {:+.2} | {} |",
+                self.names[pairing.first],
+                self.names[pairing.second],
+                result.wins,
+                result.draws,
+                result.losses,
+                result.mean_margin(),
+                EloEstimate::new(result)
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "| rank | player | points | W/D/L | mean margin | Elo |")?;
+        writeln!(f, "|------|--------|--------|-------|-------------|-----|")?;
+        for (rank, (name, result)) in self.standings().iter().enumerate() {
+            writeln!(
+                f,
+                "| {} | {} | {} | {}/{}/{} | {:+.2} | {} |",
+                rank + 1,
+                name,
+                result.wins as f64 + result.draws as f64 / 2.0,
+                result.wins,
+                result.draws,
+                result.losses,
+                result.mean_margin(),
+                EloEstimate::new(result)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Play `candidate` against `baseline` from each of `openings` in turn, once
+/// with each colour, until `sprt` accepts a hypothesis, `max_games` have been
+/// played or the openings run out. Calls `on_progress` with the candidate's
+/// results and the log-likelihood ratio after every pair of games.
+pub fn run_sprt(
+    candidate: &PlayerConfig,
+    baseline: &PlayerConfig,
+    sprt: &Sprt,
+    max_games: u32,
+    openings: impl IntoIterator<Item = Game>,
+    mut on_progress: impl FnMut(&MatchResult, f64),
+) -> (SprtDecision, MatchResult) {
+    let mut result = MatchResult::default();
+    for opening in openings {
+        if result.games() >= max_games {
+            break;
+        }
+        result.merge(&play_match(candidate, baseline, &[opening]));
+        on_progress(&result, sprt.llr(&result));
+        match sprt.decide(&result) {
+            SprtDecision::Continue => {}
+            decision => return (decision, result),
+        }
+    }
+    (SprtDecision::Continue, result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_entrant() {
+        let entrant: Entrant = "deep;search=mtd-f;depth=8;weights=score=1 capture=0.6"
+            .parse()
+            .unwrap();
+        assert_eq!(entrant.name, "deep");
+        assert_eq!(entrant.player.algorithm, Algorithm::MtdF);
+        assert_eq!(entrant.player.limit, Limit::Depth(8));
+        assert_eq!(entrant.player.weights.get("capture"), Some(0.6));
+
+        let entrant: Entrant = "quick;time=50".parse().unwrap();
+        assert_eq!(entrant.player.limit, Limit::Time(Duration::from_millis(50)));
+        assert_eq!(entrant.player.algorithm, Algorithm::AlphaBeta);
+
+        assert!("depth=3".parse::<Entrant>().is_err());
+        assert!("a;depth=deep".parse::<Entrant>().is_err());
+        assert!("a;depth=0".parse::<Entrant>().is_err());
+        assert!("a;colour=blue".parse::<Entrant>().is_err());
+    }
+
+    #[test]
+    fn pairings() {
+        assert_eq!(Format::RoundRobin.pairings(3), vec![(0, 1), (0, 2), (1, 2)]);
+        assert_eq!(Format::Gauntlet.pairings(3), vec![(0, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn standings_count_every_game_once_per_player() {
+        let entrants: Vec<Entrant> = ["a;depth=1", "b;depth=2", "c;depth=3"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let openings = [Game::default()];
+        let mut finished = 0;
+        let tournament = run(&entrants, Format::RoundRobin, &openings, |_| finished += 1);
+        assert_eq!(finished, 3);
+        let standings = tournament.standings();
+        assert_eq!(standings.len(), 3);
+        for (_, result) in &standings {
+            assert_eq!(result.games(), 4);
+        }
+        let total: i64 = standings
+            .iter()
+            .map(|(_, result)| result.total_margin)
+            .sum();
+        assert_eq!(total, 0);
+    }
+
+    #[test]
+    fn sprt_stops_at_the_game_limit() {
+        let candidate: Entrant = "a;depth=1".parse().unwrap();
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 10.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+        let openings = std::iter::repeat(Game::default());
+        let mut updates = 0;
+        let (decision, result) = run_sprt(
+            &candidate.player,
+            &candidate.player,
+            &sprt,
+            4,
+            openings,
+            |_, _| updates += 1,
+        );
+        assert_eq!(decision, SprtDecision::Continue);
+        assert_eq!(result.games(), 4);
+        assert_eq!(updates, 2);
+
+        // or when there are no more openings
+        let openings = vec![Game::default()];
+        let (_, result) = run_sprt(
+            &candidate.player,
+            &candidate.player,
+            &sprt,
+            4,
+            openings,
+            |_, _| {},
+        );
+        assert_eq!(result.games(), 2);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{
+    board::{BoardState, PlayerMove, Position},
+    heuristics::{is_decisive, Score},
+};
+
+/// Number of entries in a table created by `TranspositionTable::default()`
+pub const DEFAULT_TT_ENTRIES: usize = 1 << 20;
+
+/// How the stored score relates to the true minimax value of the position
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    /// The search failed high: the true value is at least `score`
+    Lower,
+    /// The search failed low: the true value is at most `score`
+    Upper,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Key {
+    pub board: BoardState,
+    pub pos: Position,
+    pub first_move: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub key: Key,
+    /// Number of plies searched below this position
+    pub depth: usize,
+    pub score: Score,
+    pub bound: Bound,
+    pub best_move: Option<PlayerMove>,
+}
+
+/// Forced wins and losses are scored by their distance from the root, but the
+/// same position can be reached at different plies. The table stores them by
+/// their distance from the entry's own position instead.
+pub fn score_to_tt(score: Score, ply: usize) -> Score {
+    if !is_decisive(score) {
+        score
+    } else if score > 0 {
+        score + ply as Score
+    } else {
+        score - ply as Score
+    }
+}
+
+/// Inverse of `score_to_tt` for an entry probed `ply` plies from the root
+pub fn score_from_tt(score: Score, ply: usize) -> Score {
+    if !is_decisive(score) {
+        score
+    } else if score > 0 {
+        score - ply as Score
+    } else {
+        score + ply as Score
+    }
+}
+
+/// Fixed size, always-replace transposition table.
+/// Entries store their full key, so an index collision can never return the
+/// score of a different position.
+#[derive(Debug, Clone)]
+pub struct TranspositionTable {
+    n_entries: usize,
+    /// Allocated by the first `store`, so searches that never use the table,
+    /// such as minimax and plain alpha-beta, don't pay for it
+    entries: Vec<Option<Entry>>,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self { Self::new(DEFAULT_TT_ENTRIES) }
+}
+
+impl TranspositionTable {
+    pub fn new(n_entries: usize) -> Self {
+        Self {
+            n_entries: n_entries.max(1),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize { self.n_entries }
+
+    pub fn is_empty(&self) -> bool { self.n_entries == 0 }
+
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = None;
+        }
+    }
+
+    /// FNV-1a over every pit and store, the side to move and the pie rule
+    /// flag
+    fn index(&self, key: &Key) -> usize {
+        const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0100_0000_01b3;
+
+        let sides = [key.board.north, key.board.south];
+        let bytes = sides
+            .iter()
+            .flat_map(|side| side.pits.iter().chain(Some(&side.score)))
+            .copied()
+            .chain(Some(key.pos as u8))
+            .chain(Some(key.first_move as u8));
+        let hash = bytes.fold(OFFSET, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(PRIME)
+        });
+        (hash % self.n_entries as u64) as usize
+    }
+
+    pub fn probe(&self, key: &Key) -> Option<Entry> {
+        match self.entries.get(self.index(key)) {
+            Some(Some(entry)) if entry.key == *key => Some(*entry),
+            _ => None,
+        }
+    }
+
+    pub fn store(&mut self, entry: Entry) {
+        if self.entries.is_empty() {
+            self.entries = vec![None; self.n_entries];
+        }
+        let idx = self.index(&entry.key);
+        self.entries[idx] = Some(entry);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heuristics::terminal_score;
+
+    fn entry(board: BoardState, pos: Position) -> Entry {
+        Entry {
+            key: Key {
+                board,
+                pos,
+                first_move: false,
+            },
+            depth: 3,
+            score: 1500,
+            bound: Bound::Exact,
+            best_move: Some(PlayerMove::Move { n: 2 }),
+        }
+    }
+
+    #[test]
+    fn store_then_probe() {
+        let mut tt = TranspositionTable::new(64);
+        let e = entry(BoardState::default(), Position::South);
+        assert_eq!(tt.probe(&e.key), None);
+        tt.store(e);
+        assert_eq!(tt.probe(&e.key), Some(e));
+    }
+
+    #[test]
+    fn tables_are_allocated_by_the_first_store() {
+        let mut tt = TranspositionTable::default();
+        assert_eq!(tt.len(), DEFAULT_TT_ENTRIES);
+        assert!(tt.entries.is_empty());
+        tt.clear();
+        assert!(tt.entries.is_empty());
+        tt.store(entry(BoardState::default(), Position::South));
+        assert_eq!(tt.entries.len(), DEFAULT_TT_ENTRIES);
+    }
+
+    #[test]
+    fn collisions_do_not_alias() {
+        // with a single slot every key collides
+        let mut tt = TranspositionTable::new(1);
+        let south = entry(BoardState::default(), Position::South);
+        let north = entry(BoardState::default(), Position::North);
+        tt.store(south);
+        assert_eq!(tt.probe(&north.key), None);
+        tt.store(north);
+        assert_eq!(tt.probe(&south.key), None);
+        assert_eq!(tt.probe(&north.key), Some(north));
+    }
+
+    #[test]
+    fn decisive_scores_are_stored_relative_to_the_entry() {
+        // a win 7 plies from the root, found at ply 4, is a win in 3 from there
+        for &margin in &[1, 10, -1, -10] {
+            assert_eq!(
+                score_to_tt(terminal_score(margin, 7), 4),
+                terminal_score(margin, 3)
+            );
+            assert_eq!(
+                score_from_tt(terminal_score(margin, 3), 2),
+                terminal_score(margin, 5)
+            );
+        }
+        assert_eq!(score_to_tt(1500, 4), 1500);
+        assert_eq!(score_from_tt(-1500, 4), -1500);
+    }
+}